@@ -1,5 +1,6 @@
 use crate::cache::trait_def::{Cache, CacheConfig, Info};
 use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
 use crate::error::Result;
 use std::time::SystemTime;
 
@@ -11,21 +12,39 @@ impl Cache for NoneCache {
         Ok(NoneCache)
     }
 
-    fn get_file_data(&self, _path: &str, _mtime: SystemTime) -> Option<FileData> {
+    fn get_file_data(
+        &self,
+        _path: &str,
+        _mtime: SystemTime,
+        _tokenizer: &dyn Tokenizer,
+    ) -> Option<FileData> {
         // Always return None to indicate cache miss
         None
     }
 
-    fn get_file_data_batch(&self, paths: &[(&str, SystemTime)]) -> Vec<Option<FileData>> {
+    fn get_file_data_batch(
+        &self,
+        paths: &[(&str, SystemTime)],
+        _tokenizer: &dyn Tokenizer,
+    ) -> Vec<Option<FileData>> {
         vec![None; paths.len()]
     }
 
-    fn store_file_data(&self, _file_data: &FileData, _mtime: SystemTime) -> Result<()> {
+    fn store_file_data(
+        &self,
+        _file_data: &FileData,
+        _mtime: SystemTime,
+        _tokenizer: &dyn Tokenizer,
+    ) -> Result<()> {
         // Do nothing
         Ok(())
     }
 
-    fn store_file_data_batch(&self, _batch: &[(FileData, SystemTime)]) -> Result<()> {
+    fn store_file_data_batch(
+        &self,
+        _batch: &[(FileData, SystemTime)],
+        _tokenizer: &dyn Tokenizer,
+    ) -> Result<()> {
         // Do nothing
         Ok(())
     }
@@ -1,5 +1,6 @@
 use crate::cache::trait_def::{Cache, CacheConfig, Info};
 use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
 use crate::error::{Error, Result};
 use rusqlite::{params, Connection, OpenFlags};
 use std::fs;
@@ -53,40 +54,50 @@ impl Cache for SQLiteCache {
         conn.execute("PRAGMA cache_size = -10000", []) // 10MB cache
             .map_err(|e| Error::Config(format!("Failed to set cache size: {}", e)))?;
 
+        // Token counts depend on the tokenizer, so entries are keyed by the path
+        // and the tokenizer's cache key (stored in the `encoding` column).
+        // The previous, path-only table is dropped rather than migrated.
+        conn.execute("DROP TABLE IF EXISTS file_cache", [])
+            .map_err(|e| Error::Config(format!("Failed to drop legacy cache table: {}", e)))?;
+
         // Create tables if they don't exist
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS file_cache (
-                path TEXT PRIMARY KEY,
+            "CREATE TABLE IF NOT EXISTS file_cache_v2 (
+                path TEXT NOT NULL,
+                encoding TEXT NOT NULL,
                 content TEXT NOT NULL,
                 tokens INTEGER NOT NULL,
                 mtime INTEGER NOT NULL,
-                error TEXT
+                error TEXT,
+                PRIMARY KEY (path, encoding)
             )",
             [],
         )
         .map_err(|e| Error::Config(format!("Failed to create cache table: {}", e)))?;
 
-        // Create index on path
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_file_cache_path ON file_cache(path)",
-            [],
-        )
-        .map_err(|e| Error::Config(format!("Failed to create cache index: {}", e)))?;
-
         Ok(SQLiteCache {
             conn: Arc::new(Mutex::new(conn)),
             db_path,
         })
     }
 
-    fn get_file_data(&self, path: &str, mtime: SystemTime) -> Option<FileData> {
-        self.get_file_data_batch(&[(path, mtime)])
+    fn get_file_data(
+        &self,
+        path: &str,
+        mtime: SystemTime,
+        tokenizer: &dyn Tokenizer,
+    ) -> Option<FileData> {
+        self.get_file_data_batch(&[(path, mtime)], tokenizer)
             .into_iter()
             .next()
             .flatten()
     }
 
-    fn get_file_data_batch(&self, paths: &[(&str, SystemTime)]) -> Vec<Option<FileData>> {
+    fn get_file_data_batch(
+        &self,
+        paths: &[(&str, SystemTime)],
+        tokenizer: &dyn Tokenizer,
+    ) -> Vec<Option<FileData>> {
         let conn = match self.conn.lock() {
             Ok(conn) => conn,
             Err(_) => return vec![None; paths.len()], // Lock poisoned
@@ -102,7 +113,7 @@ impl Cache for SQLiteCache {
         for chunk in paths.chunks(900) {
             let placeholders = vec!["?"; chunk.len()].join(",");
             let sql = format!(
-                "SELECT path, content, tokens, mtime, error FROM file_cache WHERE path IN ({})",
+                "SELECT path, content, tokens, mtime, error FROM file_cache_v2 WHERE encoding = ? AND path IN ({})",
                 placeholders
             );
 
@@ -111,8 +122,11 @@ impl Cache for SQLiteCache {
                 Err(_) => continue,
             };
 
+            let cache_key = tokenizer.cache_key();
             let params: Vec<&dyn rusqlite::ToSql> =
-                chunk.iter().map(|(p, _)| p as &dyn rusqlite::ToSql).collect();
+                std::iter::once(&cache_key as &dyn rusqlite::ToSql)
+                    .chain(chunk.iter().map(|(p, _)| p as &dyn rusqlite::ToSql))
+                    .collect();
 
             let mut rows = match stmt.query(params.as_slice()) {
                 Ok(rows) => rows,
@@ -144,11 +158,20 @@ impl Cache for SQLiteCache {
         results
     }
 
-    fn store_file_data(&self, file_data: &FileData, mtime: SystemTime) -> Result<()> {
-        self.store_file_data_batch(&[(file_data.clone(), mtime)])
+    fn store_file_data(
+        &self,
+        file_data: &FileData,
+        mtime: SystemTime,
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<()> {
+        self.store_file_data_batch(&[(file_data.clone(), mtime)], tokenizer)
     }
 
-    fn store_file_data_batch(&self, batch: &[(FileData, SystemTime)]) -> Result<()> {
+    fn store_file_data_batch(
+        &self,
+        batch: &[(FileData, SystemTime)],
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<()> {
         let mut conn = self.conn.lock().map_err(|_| {
             Error::Config("Failed to acquire lock on SQLite connection".to_string())
         })?;
@@ -160,7 +183,7 @@ impl Cache for SQLiteCache {
         {
             let mut stmt = tx
                 .prepare_cached(
-                    "INSERT OR REPLACE INTO file_cache (path, content, tokens, mtime, error) VALUES (?, ?, ?, ?, ?)",
+                    "INSERT OR REPLACE INTO file_cache_v2 (path, encoding, content, tokens, mtime, error) VALUES (?, ?, ?, ?, ?, ?)",
                 )
                 .map_err(|e| Error::Config(format!("Failed to prepare statement: {}", e)))?;
 
@@ -168,6 +191,7 @@ impl Cache for SQLiteCache {
                 let mtime_ts = Self::system_time_to_timestamp(*mtime);
                 stmt.execute(params![
                     file_data.path,
+                    tokenizer.cache_key(),
                     file_data.content,
                     file_data.tokens,
                     mtime_ts,
@@ -188,7 +212,7 @@ impl Cache for SQLiteCache {
             Error::Config("Failed to acquire lock on SQLite connection".to_string())
         })?;
 
-        conn.execute("DELETE FROM file_cache", [])
+        conn.execute("DELETE FROM file_cache_v2", [])
             .map_err(|e| Error::Config(format!("Failed to clear cache: {}", e)))?;
 
         Ok(())
@@ -203,7 +227,7 @@ impl Cache for SQLiteCache {
 
         // Get the number of Records in the table.
         let result = conn.query_row(
-            "SELECT COUNT(*) FROM file_cache", //Efficient way to get row count
+            "SELECT COUNT(*) FROM file_cache_v2", //Efficient way to get row count
             params![],
             |row| {
                 let total: i64 = row.get(0)?;
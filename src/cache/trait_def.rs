@@ -1,6 +1,7 @@
 use serde::Serialize;
 
 use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
 use crate::error::Result;
 use std::path::PathBuf;
 use std::time::SystemTime;
@@ -32,35 +33,57 @@ pub trait Cache: Send + Sync {
     /// # Arguments
     /// * `path` - The path to the file
     /// * `mtime` - The last modification time of the file
+    /// * `tokenizer` - The tokenizer the cached token count must have been computed with
     ///
     /// # Returns
     /// * `Some(FileData)` if the file is in the cache and not modified
     /// * `None` if the file is not in the cache or has been modified
     #[allow(dead_code)]
-    fn get_file_data(&self, path: &str, mtime: SystemTime) -> Option<FileData>;
+    fn get_file_data(
+        &self,
+        path: &str,
+        mtime: SystemTime,
+        tokenizer: &dyn Tokenizer,
+    ) -> Option<FileData>;
 
     /// Get multiple file data from the cache in one go
     ///
     /// # Arguments
     /// * `paths` - A slice of (path, mtime) pairs
+    /// * `tokenizer` - The tokenizer the cached token counts must have been computed with
     ///
     /// # Returns
     /// * A vector of `Option<FileData>` in the same order as paths
-    fn get_file_data_batch(&self, paths: &[(&str, SystemTime)]) -> Vec<Option<FileData>>;
+    fn get_file_data_batch(
+        &self,
+        paths: &[(&str, SystemTime)],
+        tokenizer: &dyn Tokenizer,
+    ) -> Vec<Option<FileData>>;
 
     /// Store file data in the cache
     ///
     /// # Arguments
     /// * `file_data` - The file data to store
     /// * `mtime` - The last modification time of the file
+    /// * `tokenizer` - The tokenizer the token count was computed with
     #[allow(dead_code)]
-    fn store_file_data(&self, file_data: &FileData, mtime: SystemTime) -> Result<()>;
+    fn store_file_data(
+        &self,
+        file_data: &FileData,
+        mtime: SystemTime,
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<()>;
 
     /// Store a batch of file data in the cache efficiently
     ///
     /// # Arguments
     /// * `batch` - A vector of (file_data, mtime) pairs
-    fn store_file_data_batch(&self, batch: &[(FileData, SystemTime)]) -> Result<()>;
+    /// * `tokenizer` - The tokenizer the token counts were computed with
+    fn store_file_data_batch(
+        &self,
+        batch: &[(FileData, SystemTime)],
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<()>;
 
     /// Clear the cache
     fn clear(&self) -> Result<()>;
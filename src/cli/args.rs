@@ -64,6 +64,15 @@ pub struct Cli {
     #[arg(long)]
     pub clear_cache: bool,
 
+    /// Tokenizer: an encoding (cl100k_base, o200k_base, p50k_base, r50k_base), a model name,
+    /// or the path of a HuggingFace tokenizer.json file
+    #[arg(long, global = true)]
+    pub tokenizer: Option<String>,
+
+    /// Approximate token counts with a fast heuristic instead of running the tokenizer
+    #[arg(long, global = true, conflicts_with = "tokenizer")]
+    pub estimate: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -91,8 +100,8 @@ pub enum Commands {
         #[arg(default_value = ".")]
         path: PathBuf,
 
-        /// Glob filters to include
-        #[arg(short = 'f', long, num_args = 1.., default_value = "**")]
+        /// Glob filters to include (default: the config filters, or **)
+        #[arg(short = 'f', long, num_args = 1..)]
         filters: Vec<String>,
 
         /// Patterns to ignore
@@ -104,9 +113,42 @@ pub enum Commands {
         output: Option<PathBuf>,
 
         /// Output format
-        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        #[arg(long, default_value = "text", value_parser = ["text", "markdown", "xml", "json", "jsonl", "tar", "zip"])]
         format: String,
 
+        /// Render output through a template file (overrides --format)
+        #[arg(long)]
+        template: Option<PathBuf>,
+
+        /// Prefix every line with its line number
+        #[arg(long = "line-numbers")]
+        line_numbers: bool,
+
+        /// Include the directory tree of the selected files
+        #[arg(long = "with-tree")]
+        with_tree: bool,
+
+        /// Include the token statistics of the selected files
+        #[arg(long = "with-stats")]
+        with_stats: bool,
+
+        /// Wrap json output in an object that records the tokenizer
+        #[arg(long = "with-meta")]
+        with_meta: bool,
+
+        /// Split output into numbered parts of at most N tokens each
+        #[arg(
+            long = "split-tokens",
+            value_name = "N",
+            requires = "output",
+            conflicts_with = "template"
+        )]
+        split_tokens: Option<usize>,
+
+        /// Allow cutting files larger than --split-tokens on line boundaries
+        #[arg(long = "allow-file-split", requires = "split_tokens")]
+        allow_file_split: bool,
+
         /// Maximum token budget
         #[arg(long = "max-budget", short = 'M', default_value_t = 10000)]
         max_budget: usize,
@@ -123,6 +165,36 @@ pub enum Commands {
         #[arg(long = "limit-by-low-budget", short = 'L')]
         limit_by_low_budget: bool,
 
+        /// Keep empty and whitespace-only files instead of skipping them
+        #[arg(long = "include-empty")]
+        include_empty: bool,
+
+        /// Exit with an error when any file could not be read
+        #[arg(long = "fail-on-error")]
+        fail_on_error: bool,
+
+        /// Cut files over --max-budget down to it instead of dropping them, keeping
+        /// the head, or the head and tail around the dropped middle (alias: middle)
+        #[arg(long, value_name = "STRATEGY", value_parser = ["head", "head-tail", "middle"], requires = "limit_by_high_budget", conflicts_with = "rendered_budget")]
+        truncate: Option<String>,
+
+        /// Keep the rendered output, headers, framing and preamble included, under N tokens
+        #[arg(long = "total-budget", value_name = "N")]
+        total_budget: Option<usize>,
+
+        /// How files are picked under --total-budget
+        #[arg(long, default_value = "path", value_parser = ["path", "smallest", "largest", "knapsack"], requires = "total_budget")]
+        strategy: String,
+
+        /// Report every candidate file left out, with the rule that excluded it, to stderr
+        #[arg(long = "report-excluded")]
+        report_excluded: bool,
+
+        /// Apply the budget limits to each file's rendered cost (headers, fences or XML
+        /// wrappers included) instead of its raw content
+        #[arg(long = "rendered-budget")]
+        rendered_budget: bool,
+
         /// Use specific config context
         #[arg(long)]
         context: Option<String>,
@@ -138,6 +210,19 @@ pub enum Commands {
         /// Read from stdin
         #[arg(long)]
         input: bool,
+
+        /// Check that the output fits the context window of this model
+        /// (repeatable; MODEL=TOKENS for models without a built-in window)
+        #[arg(long, value_name = "MODEL")]
+        fit: Vec<String>,
+
+        /// Tokens to reserve for the response when checking --fit
+        #[arg(long, value_name = "N", default_value_t = 0, requires = "fit")]
+        reserve: usize,
+
+        /// Exit with an error when the output overflows a --fit model
+        #[arg(long, requires = "fit")]
+        strict: bool,
     },
 
     /// Display file tree structure
@@ -146,8 +231,8 @@ pub enum Commands {
         #[arg(default_value = ".")]
         path: PathBuf,
 
-        /// Glob filters
-        #[arg(short = 'f', long, num_args = 1.., default_value = "**")]
+        /// Glob filters (default: the config filters, or **)
+        #[arg(short = 'f', long, num_args = 1..)]
         filters: Vec<String>,
 
         /// Ignore patterns
@@ -170,6 +255,26 @@ pub enum Commands {
         #[arg(long = "limit-by-low-budget", short = 'L')]
         limit_by_low_budget: bool,
 
+        /// Keep empty and whitespace-only files instead of skipping them
+        #[arg(long = "include-empty")]
+        include_empty: bool,
+
+        /// Exit with an error when any file could not be read
+        #[arg(long = "fail-on-error")]
+        fail_on_error: bool,
+
+        /// Keep the combined tokens of the selected files under N
+        #[arg(long = "total-budget", value_name = "N")]
+        total_budget: Option<usize>,
+
+        /// How files are picked under --total-budget
+        #[arg(long, default_value = "path", value_parser = ["path", "smallest", "largest", "knapsack"], requires = "total_budget")]
+        strategy: String,
+
+        /// Report every candidate file left out, with the rule that excluded it, to stderr
+        #[arg(long = "report-excluded")]
+        report_excluded: bool,
+
         /// Use specific config context
         #[arg(long)]
         context: Option<String>,
@@ -185,6 +290,10 @@ pub enum Commands {
         /// Read from stdin
         #[arg(long)]
         input: bool,
+
+        /// Output format
+        #[arg(long, default_value = "plain", value_parser = ["plain", "html"])]
+        format: String,
     },
 
     /// Calculate token counts
@@ -193,8 +302,8 @@ pub enum Commands {
         #[arg(default_value = ".")]
         path: PathBuf,
 
-        /// Glob filters
-        #[arg(short = 'f', long, num_args = 1.., default_value = "**")]
+        /// Glob filters (default: the config filters, or **)
+        #[arg(short = 'f', long, num_args = 1..)]
         filters: Vec<String>,
 
         /// Ignore patterns
@@ -221,6 +330,26 @@ pub enum Commands {
         #[arg(long = "limit-by-low-budget", short = 'L')]
         limit_by_low_budget: bool,
 
+        /// Keep empty and whitespace-only files instead of skipping them
+        #[arg(long = "include-empty")]
+        include_empty: bool,
+
+        /// Exit with an error when any file could not be read
+        #[arg(long = "fail-on-error")]
+        fail_on_error: bool,
+
+        /// Keep the combined tokens of the selected files under N
+        #[arg(long = "total-budget", value_name = "N")]
+        total_budget: Option<usize>,
+
+        /// How files are picked under --total-budget
+        #[arg(long, default_value = "path", value_parser = ["path", "smallest", "largest", "knapsack"], requires = "total_budget")]
+        strategy: String,
+
+        /// Report every candidate file left out, with the rule that excluded it, to stderr
+        #[arg(long = "report-excluded")]
+        report_excluded: bool,
+
         /// Use specific config context
         #[arg(long)]
         context: Option<String>,
@@ -237,9 +366,102 @@ pub enum Commands {
         #[arg(long)]
         input: bool,
 
+        /// Output format
+        #[arg(long, default_value = "plain", value_parser = ["plain", "json", "jsonl", "html"])]
+        format: String,
+
+        /// Show the token boundaries and most expensive lines of a single file
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["stats", "group_by", "price", "fit"])]
+        explain: Option<PathBuf>,
+
+        /// Show the distribution of tokens per file and the files excluded by each budget
+        /// rule instead of the largest files
+        #[arg(long, conflicts_with_all = ["group_by", "price"])]
+        stats: bool,
+
+        /// Aggregate token counts by file extension, language or directory
+        #[arg(long = "group-by", value_parser = ["ext", "lang", "dir"], conflicts_with = "price")]
+        group_by: Option<String>,
+
+        /// Number of leading directories that make a group with --group-by dir
+        #[arg(long, default_value_t = 1, requires = "group_by")]
+        depth: usize,
+
+        /// Show the estimated prompt cost for this model (plain and json formats)
+        #[arg(long, value_name = "MODEL")]
+        price: Option<String>,
+
+        /// YAML file with model prices that extend or override the built-in table
+        #[arg(long, value_name = "FILE", requires = "price")]
+        pricing: Option<PathBuf>,
+
+        /// Check that the output fits the context window of this model
+        /// (repeatable; MODEL=TOKENS for models without a built-in window)
+        #[arg(long, value_name = "MODEL")]
+        fit: Vec<String>,
+
+        /// Tokens to reserve for the response when checking --fit
+        #[arg(long, value_name = "N", default_value_t = 0, requires = "fit")]
+        reserve: usize,
+
+        /// Exit with an error when the output overflows a --fit model
+        #[arg(long, requires = "fit")]
+        strict: bool,
+
+        /// Merge format whose formatting overhead is counted by --fit
+        #[arg(long = "fit-format", default_value = "text", value_parser = ["text", "markdown", "xml", "json"], requires = "fit")]
+        fit_format: String,
+    },
+
+    /// Count the tokens of raw text, such as a prompt draft or a diff
+    Count {
+        /// File to read the text from ("-" for stdin)
+        #[arg(default_value = "-")]
+        input: PathBuf,
+
+        /// Also show the tokens of every line
+        #[arg(long = "per-line")]
+        per_line: bool,
+
         /// Output format
         #[arg(long, default_value = "plain", value_parser = ["plain", "json"])]
         format: String,
+
+        /// Use specific config context
+        #[arg(long)]
+        context: Option<String>,
+
+        /// Ignore config file
+        #[arg(long = "ignore-config")]
+        ignore_config: bool,
+
+        /// Alternative config path
+        #[arg(long = "config-path")]
+        config_path: Option<PathBuf>,
+    },
+
+    /// Reconstruct files from merged output
+    #[command(alias = "split")]
+    Unmerge {
+        /// Merged output to read ("-" for stdin)
+        #[arg(default_value = "-")]
+        input: PathBuf,
+
+        /// Directory to write the files into
+        #[arg(short = 'o', long, default_value = ".")]
+        target: PathBuf,
+
+        /// Format of the merged output
+        #[arg(long, default_value = "auto", value_parser = ["auto", "text", "markdown", "xml", "json"])]
+        format: String,
+
+        /// Show a diff against the target directory instead of writing files
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Restore files merged from an absolute or .. path relative to the directory they share
+        #[arg(long = "strip-root")]
+        strip_root: bool,
     },
 
     /// Initialize configuration
@@ -253,3 +475,33 @@ pub enum Commands {
         force: bool,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_split_tokens_conflicts_with_template() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["codemerge", "merge"], args].concat());
+
+        let error = parse(&[
+            "--template",
+            "t.tera",
+            "--split-tokens",
+            "100",
+            "--output",
+            "out",
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(parse(&["--split-tokens", "100", "--output", "out"]).is_ok());
+        assert!(parse(&["--template", "t.tera", "--output", "out"]).is_ok());
+    }
+}
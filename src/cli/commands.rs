@@ -4,13 +4,28 @@
 //! the core business logic, such as merging files, analyzing tokens,
 //! generating trees, and managing the cache.
 
-use crate::cache::{self, Info};
+use crate::cache::{self, Cache, Info};
 use crate::cli::args::{CacheOperation, CacheProvider, Cli, Commands};
 use crate::config::{self, Config};
-use crate::core::{file, tokens, tree};
-use crate::error::Result;
-use crate::utils::{filters, finder, format};
+use crate::core::file::{self, FileData};
+use crate::core::fit::{self, FitReport};
+use crate::core::group::{self, GroupBy};
+use crate::core::pricing::PricingTable;
+use crate::core::stats;
+use crate::core::tokenizer::{self, Encoding, Estimator, Tokenizer};
+use crate::core::tokens;
+use crate::core::tree;
+use crate::core::truncate::{self, Truncation};
+use crate::error::{Error, Result};
+use crate::utils::exclusion::{self, Exclusion, PatternSource};
+use crate::utils::priority::Priorities;
+use crate::utils::selection::{self, Strategy};
+use crate::utils::template::Template;
+use crate::utils::{archive, filters, finder, format, html, split, unmerge};
+use rayon::prelude::*;
+use std::io::{IsTerminal, Write};
 use std::path::Path;
+use std::sync::Mutex;
 
 pub fn execute(cli: Cli) -> Result<()> {
     // Initialize cache if not disabled
@@ -69,42 +84,279 @@ pub fn execute(cli: Cli) -> Result<()> {
             ignores,
             output,
             format: format_type,
+            template,
+            line_numbers,
+            with_tree,
+            with_stats,
+            with_meta,
+            split_tokens,
+            allow_file_split,
             max_budget,
             min_budget,
             limit_by_high_budget,
             limit_by_low_budget,
+            include_empty,
+            fail_on_error,
+            truncate,
+            total_budget,
+            strategy,
+            rendered_budget,
+            report_excluded,
             context,
             ignore_config,
             config_path,
             input,
+            fit,
+            reserve,
+            strict,
         } => {
             let config = if !ignore_config {
                 config::load_config(config_path.as_deref(), context.as_deref())?
             } else {
                 Config::default()
             };
+            let tokenizer = resolve_tokenizer(cli.tokenizer.as_deref(), cli.estimate, &config)?;
+            let tokenizer = tokenizer.as_ref();
+            let priorities = Priorities::new(&config.priorities, &path)?;
+            for model in &fit {
+                fit::context_window(model)?;
+            }
+
+            let (files, mut excluded) = find_candidates(
+                &path,
+                &filter_patterns,
+                &ignores,
+                &config,
+                input,
+                report_excluded,
+            )?;
+
+            if !fit.is_empty() && matches!(format_type.as_str(), "jsonl" | "tar" | "zip") {
+                return Err(Error::Config(format!(
+                    "--fit is not supported by the {} format",
+                    format_type
+                )));
+            }
 
-            let files = if input || finder::has_stdin_pipe() {
-                finder::read_from_stdin()?
+            if matches!(format_type.as_str(), "tar" | "zip") {
+                // Archives store the files as they are on disk.
+                for (flag, set) in [
+                    ("--line-numbers", line_numbers),
+                    ("--truncate", truncate.is_some()),
+                ] {
+                    if set {
+                        return Err(Error::Config(format!(
+                            "{} is not supported by the {} format",
+                            flag, format_type
+                        )));
+                    }
+                }
+            }
+
+            let template = template.or(config.template);
+            if template.is_some() && split_tokens.is_some() {
+                return Err(Error::Config(
+                    "--split-tokens cannot be combined with a template".to_string(),
+                ));
+            }
+            if rendered_budget
+                && (template.is_some() || matches!(format_type.as_str(), "jsonl" | "tar" | "zip"))
+            {
+                return Err(Error::Config(
+                    "--rendered-budget requires the text, markdown, xml or json format".to_string(),
+                ));
+            }
+
+            if total_budget.is_some() && format_type == "jsonl" {
+                return Err(Error::Config(
+                    "--total-budget is not supported by the jsonl format".to_string(),
+                ));
+            }
+            if report_excluded && format_type == "jsonl" {
+                return Err(Error::Config(
+                    "--report-excluded is not supported by the jsonl format".to_string(),
+                ));
+            }
+            let truncation = truncate.as_deref().map(Truncation::from_name).transpose()?;
+
+            if format_type == "jsonl" {
+                let errors = stream_jsonl(
+                    &files,
+                    cache.as_deref(),
+                    tokenizer,
+                    format::open_output(output).map_err(output_error)?,
+                    true,
+                    |fd| {
+                        let fd = if line_numbers {
+                            fd.with_line_numbers(tokenizer)
+                        } else {
+                            fd
+                        };
+                        let fd = match truncation {
+                            Some(truncation) => {
+                                truncate_oversized(fd, max_budget, truncation, tokenizer)
+                            }
+                            None => fd,
+                        };
+                        filters::passes_budget(
+                            &fd,
+                            min_budget,
+                            max_budget,
+                            limit_by_high_budget,
+                            limit_by_low_budget,
+                            include_empty,
+                        )
+                        .then_some(fd)
+                    },
+                )?;
+                report_read_errors(&errors);
+                return filters::enforce_readable(&errors, fail_on_error);
+            }
+
+            let mut processed = file::process_files(&files, cache.as_deref(), tokenizer);
+            let errors = report_read_errors(&processed);
+            filters::enforce_readable(&errors, fail_on_error)?;
+            if line_numbers {
+                processed = processed
+                    .into_iter()
+                    .map(|fd| fd.with_line_numbers(tokenizer))
+                    .collect();
+            }
+            if let Some(truncation) = truncation {
+                processed = processed
+                    .into_par_iter()
+                    .map(|fd| truncate_oversized(fd, max_budget, truncation, tokenizer))
+                    .collect();
+            }
+            let costs = if rendered_budget {
+                rendered_costs(&processed, &format_type, tokenizer)?
             } else {
-                finder::find_files(
-                    &path,
-                    &merge_patterns(&filter_patterns, &config.filters),
-                    &merge_patterns(&ignores, &config.ignores),
-                )?
+                processed.iter().map(|f| f.tokens).collect()
             };
-
-            let processed = file::process_files(&files, cache.as_ref());
-            let filtered = filters::apply_budget_filters(
+            if report_excluded {
+                excluded.extend(filters::budget_exclusions(
+                    &processed,
+                    &costs,
+                    min_budget,
+                    max_budget,
+                    limit_by_high_budget,
+                    limit_by_low_budget,
+                    include_empty,
+                ));
+            }
+            let filtered = filters::apply_budget_filters_with_costs(
                 processed,
+                &costs,
                 min_budget,
                 max_budget,
                 limit_by_high_budget,
                 limit_by_low_budget,
+                include_empty,
             );
+            if report_excluded {
+                eprint!("{}", exclusion::format_report(&excluded, filtered.len()));
+            }
+            let options = format::OutputOptions {
+                with_tree: with_tree || config.with_tree,
+                with_stats: with_stats || config.with_stats,
+                with_meta,
+                tokenizer,
+                errors: &errors,
+            };
+            let mut filtered = match total_budget {
+                // A rendered document spends the budget on the headers and
+                // fences around each file too, and on its framing and preamble.
+                Some(budget)
+                    if template.is_none()
+                        && matches!(format_type.as_str(), "text" | "markdown" | "xml" | "json") =>
+                {
+                    let costs = rendered_costs(&filtered, &format_type, tokenizer)?;
+                    let shared = format::shared_cost(&filtered, &format_type, &options)
+                        .map_err(output_error)?;
+                    if shared > 0 {
+                        eprintln!(
+                            "Total budget {}: {} tokens go to the document framing and preamble, {} to files",
+                            budget,
+                            shared,
+                            budget.saturating_sub(shared)
+                        );
+                    }
+                    let budget = Some(budget.saturating_sub(shared));
+                    apply_total_budget(filtered, &costs, budget, &strategy, &priorities)?
+                }
+                Some(_) => {
+                    let costs: Vec<usize> = filtered.iter().map(|f| f.tokens).collect();
+                    apply_total_budget(filtered, &costs, total_budget, &strategy, &priorities)?
+                }
+                None => filtered,
+            };
+            priorities.sort(&mut filtered);
+
+            if let Some(template_path) = template {
+                let rendered =
+                    Template::load(&template_path)?.render(&filtered, &path, tokenizer)?;
+                let rendered_tokens = tokenizer.count(&rendered);
+                report_rendered(rendered_tokens, "template", tokenizer);
+                check_fit(
+                    &fit,
+                    rendered_tokens,
+                    reserve,
+                    strict,
+                    "template",
+                    tokenizer,
+                )?;
+                format::write_output(&rendered, output).map_err(output_error)?;
+                return Ok(());
+            }
 
-            format::output_results(&filtered, &format_type, output)
-                .map_err(|e| crate::error::Error::Config(format!("Output error: {}", e)))?;
+            if matches!(format_type.as_str(), "tar" | "zip") {
+                let output = output.ok_or_else(|| {
+                    Error::Config(format!("The {} format requires --output", format_type))
+                })?;
+                archive::write_archive(&filtered, &format_type, &path, &output, tokenizer)
+                    .map_err(output_error)?;
+                return Ok(());
+            }
+
+            if let (Some(max_tokens), Some(output)) = (split_tokens, output.as_deref()) {
+                let parts = split::split_files(
+                    &filtered,
+                    &format_type,
+                    &options,
+                    max_tokens,
+                    allow_file_split,
+                )
+                .map_err(output_error)?;
+                let largest = parts.iter().map(|part| part.tokens).max().unwrap_or(0);
+                check_fit(&fit, largest, reserve, strict, &format_type, tokenizer)?;
+                let part_tokens =
+                    split::write_parts(&parts, &format_type, &options, output, max_tokens)
+                        .map_err(output_error)?;
+                report_rendered(
+                    part_tokens.iter().sum(),
+                    &format!("{} format, {} parts", format_type, part_tokens.len()),
+                    tokenizer,
+                );
+                return Ok(());
+            }
+
+            let rendered =
+                format::render(&filtered, &format_type, &options).map_err(output_error)?;
+            let rendered_tokens = tokenizer.count(&rendered);
+            report_rendered(
+                rendered_tokens,
+                &format!("{} format", format_type),
+                tokenizer,
+            );
+            check_fit(
+                &fit,
+                rendered_tokens,
+                reserve,
+                strict,
+                &format_type,
+                tokenizer,
+            )?;
+            format::write_output(&rendered, output).map_err(output_error)?;
             Ok(())
         }
 
@@ -116,39 +368,88 @@ pub fn execute(cli: Cli) -> Result<()> {
             min_budget,
             limit_by_high_budget,
             limit_by_low_budget,
+            include_empty,
+            fail_on_error,
+            total_budget,
+            strategy,
+            report_excluded,
             context,
             ignore_config,
             config_path,
             input,
+            format,
         } => {
             let config = if !ignore_config {
                 config::load_config(config_path.as_deref(), context.as_deref())?
             } else {
                 Config::default()
             };
+            let tokenizer = resolve_tokenizer(cli.tokenizer.as_deref(), cli.estimate, &config)?;
+            let tokenizer = tokenizer.as_ref();
+            let priorities = Priorities::new(&config.priorities, &path)?;
 
-            let files = if input || finder::has_stdin_pipe() {
-                finder::read_from_stdin()?
-            } else {
-                finder::find_files(
-                    &path,
-                    &merge_patterns(&filter_patterns, &config.filters),
-                    &merge_patterns(&ignores, &config.ignores),
-                )?
-            };
+            let (files, mut excluded) = find_candidates(
+                &path,
+                &filter_patterns,
+                &ignores,
+                &config,
+                input,
+                report_excluded,
+            )?;
 
-            let processed = file::process_files(&files, cache.as_ref());
+            let processed = file::process_files(&files, cache.as_deref(), tokenizer);
+            let errors = report_read_errors(&processed);
+            if report_excluded {
+                let costs: Vec<usize> = processed.iter().map(|f| f.tokens).collect();
+                excluded.extend(filters::budget_exclusions(
+                    &processed,
+                    &costs,
+                    min_budget,
+                    max_budget,
+                    limit_by_high_budget,
+                    limit_by_low_budget,
+                    include_empty,
+                ));
+            }
             let filtered = filters::apply_budget_filters(
                 processed,
                 min_budget,
                 max_budget,
                 limit_by_high_budget,
                 limit_by_low_budget,
+                include_empty,
             );
+            if report_excluded {
+                eprint!("{}", exclusion::format_report(&excluded, filtered.len()));
+            }
+            let filtered =
+                apply_total_budget_by_tokens(filtered, total_budget, &strategy, &priorities)?;
 
-            let tree_structure = tree::build_tree(&filtered);
-            println!("{}", tree::format_tree(&tree_structure, "", true));
-            Ok(())
+            match format.as_str() {
+                "plain" => {
+                    let mut tree_structure = tree::build_tree(&filtered);
+                    if !priorities.is_empty() {
+                        tree::assign_priorities(&mut tree_structure, &priorities);
+                    }
+                    println!("{}", tree::format_tree(&tree_structure, "", true));
+                    if tokenizer.is_estimate() {
+                        println!("Token counts are estimated (--estimate)");
+                    }
+                }
+                "html" => {
+                    print!(
+                        "{}",
+                        html::format_html_report(
+                            &filtered,
+                            &report_title(&path),
+                            filtered.len(),
+                            tokenizer,
+                        )
+                    );
+                }
+                _ => unreachable!("Invalid format option"),
+            }
+            filters::enforce_readable(&errors, fail_on_error)
         }
 
         Commands::Tokens {
@@ -160,46 +461,310 @@ pub fn execute(cli: Cli) -> Result<()> {
             min_budget,
             limit_by_high_budget,
             limit_by_low_budget,
+            include_empty,
+            fail_on_error,
+            total_budget,
+            strategy,
+            report_excluded,
             context,
             ignore_config,
             config_path,
             input,
             format,
+            explain,
+            stats,
+            group_by,
+            depth,
+            price,
+            pricing,
+            fit,
+            reserve,
+            strict,
+            fit_format,
         } => {
             let config = if !ignore_config {
                 config::load_config(config_path.as_deref(), context.as_deref())?
             } else {
                 Config::default()
             };
+            let tokenizer = resolve_tokenizer(cli.tokenizer.as_deref(), cli.estimate, &config)?;
+            let tokenizer = tokenizer.as_ref();
+            let priorities = Priorities::new(&config.priorities, &path)?;
+            let shown_priorities = (!priorities.is_empty()).then_some(&priorities);
 
-            let files = if input || finder::has_stdin_pipe() {
-                finder::read_from_stdin()?
-            } else {
-                finder::find_files(
-                    &path,
-                    &merge_patterns(&filter_patterns, &config.filters),
-                    &merge_patterns(&ignores, &config.ignores),
-                )?
+            if let Some(explain) = explain {
+                return explain_file(&explain, &format, total, tokenizer);
+            }
+
+            let price = match price {
+                Some(model) => {
+                    if !matches!(format.as_str(), "plain" | "json") {
+                        return Err(Error::Config(format!(
+                            "--price is not supported by the {} format",
+                            format
+                        )));
+                    }
+                    let table = match pricing.or(config.pricing.clone()) {
+                        Some(path) => PricingTable::load(&path)?,
+                        None => PricingTable::default(),
+                    };
+                    Some(table.price(&model)?)
+                }
+                None => None,
             };
+            if !fit.is_empty() && !matches!(format.as_str(), "plain" | "json") {
+                return Err(Error::Config(format!(
+                    "--fit is not supported by the {} format",
+                    format
+                )));
+            }
+            for model in &fit {
+                fit::context_window(model)?;
+            }
+            if stats && !matches!(format.as_str(), "plain" | "json") {
+                return Err(Error::Config(format!(
+                    "--stats is not supported by the {} format",
+                    format
+                )));
+            }
+            let group_by = match group_by {
+                Some(name) if matches!(format.as_str(), "plain" | "json") => {
+                    Some(GroupBy::from_name(&name, depth)?)
+                }
+                Some(_) => {
+                    return Err(Error::Config(format!(
+                        "--group-by is not supported by the {} format",
+                        format
+                    )))
+                }
+                None => None,
+            };
+
+            let (files, mut excluded) = find_candidates(
+                &path,
+                &filter_patterns,
+                &ignores,
+                &config,
+                input,
+                report_excluded,
+            )?;
+
+            if total_budget.is_some() && format == "jsonl" {
+                return Err(Error::Config(
+                    "--total-budget is not supported by the jsonl format".to_string(),
+                ));
+            }
+            if report_excluded && format == "jsonl" {
+                return Err(Error::Config(
+                    "--report-excluded is not supported by the jsonl format".to_string(),
+                ));
+            }
 
-            let processed = file::process_files(&files, cache.as_ref());
+            if format == "jsonl" {
+                let errors = stream_jsonl(
+                    &files,
+                    cache.as_deref(),
+                    tokenizer,
+                    format::open_output(None).map_err(output_error)?,
+                    false,
+                    |fd| {
+                        filters::passes_budget(
+                            &fd,
+                            min_budget,
+                            max_budget,
+                            limit_by_high_budget,
+                            limit_by_low_budget,
+                            include_empty,
+                        )
+                        .then_some(fd)
+                    },
+                )?;
+                report_read_errors(&errors);
+                return filters::enforce_readable(&errors, fail_on_error);
+            }
+
+            let processed = file::process_files(&files, cache.as_deref(), tokenizer);
+            let errors = report_read_errors(&processed);
+            // The distribution covers every file before the budget filters,
+            // and only needs their paths and token counts.
+            let unfiltered: Vec<FileData> = if stats {
+                processed
+                    .iter()
+                    .map(|f| FileData {
+                        content: String::new(),
+                        ..f.clone()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            if report_excluded {
+                let costs: Vec<usize> = processed.iter().map(|f| f.tokens).collect();
+                excluded.extend(filters::budget_exclusions(
+                    &processed,
+                    &costs,
+                    min_budget,
+                    max_budget,
+                    limit_by_high_budget,
+                    limit_by_low_budget,
+                    include_empty,
+                ));
+            }
             let filtered = filters::apply_budget_filters(
                 processed,
                 min_budget,
                 max_budget,
                 limit_by_high_budget,
                 limit_by_low_budget,
+                include_empty,
             );
+            if report_excluded {
+                eprint!("{}", exclusion::format_report(&excluded, filtered.len()));
+            }
+            let filtered =
+                apply_total_budget_by_tokens(filtered, total_budget, &strategy, &priorities)?;
+
+            let fit_report = if fit.is_empty() {
+                None
+            } else {
+                let options = format::OutputOptions {
+                    tokenizer,
+                    ..Default::default()
+                };
+                let rendered =
+                    format::render(&filtered, &fit_format, &options).map_err(output_error)?;
+                Some(FitReport::new(&fit, tokenizer.count(&rendered), reserve)?)
+            };
 
             match format.as_str() {
                 "plain" => {
-                    print!("{}", tokens::format_token_board(&filtered, total));
+                    let board = match group_by {
+                        _ if stats => stats::format_stats_board(
+                            &unfiltered,
+                            min_budget,
+                            max_budget,
+                            limit_by_high_budget,
+                            limit_by_low_budget,
+                            tokenizer,
+                        ),
+                        Some(group_by) => {
+                            group::format_group_board(&filtered, group_by, &path, total, tokenizer)
+                        }
+                        None => tokens::format_token_board(
+                            &filtered,
+                            total,
+                            tokenizer,
+                            price.as_ref(),
+                            shown_priorities,
+                        ),
+                    };
+                    print!("{}", board);
+                    if let Some(report) = &fit_report {
+                        print!("\n{}", report.format(&fit_format, tokenizer.is_estimate()));
+                    }
                 }
                 "json" => {
-                    println!("{}", tokens::format_token_json(&filtered, total));
+                    let json = match group_by {
+                        _ if stats => stats::format_stats_json(
+                            &unfiltered,
+                            min_budget,
+                            max_budget,
+                            limit_by_high_budget,
+                            limit_by_low_budget,
+                            tokenizer,
+                            fit_report.as_ref(),
+                        ),
+                        Some(group_by) => group::format_group_json(
+                            &filtered,
+                            group_by,
+                            &path,
+                            total,
+                            tokenizer,
+                            fit_report.as_ref(),
+                        ),
+                        None => tokens::format_token_json(
+                            &filtered,
+                            total,
+                            tokenizer,
+                            price.as_ref(),
+                            shown_priorities,
+                            fit_report.as_ref(),
+                        ),
+                    };
+                    println!("{}", with_read_errors(json, &errors)?);
+                }
+                "html" => {
+                    print!(
+                        "{}",
+                        html::format_html_report(&filtered, &report_title(&path), total, tokenizer)
+                    );
                 }
                 _ => unreachable!("Invalid format option"),
             }
+            if let (Some(report), true) = (&fit_report, strict) {
+                enforce_fit(report)?;
+            }
+            filters::enforce_readable(&errors, fail_on_error)
+        }
+
+        Commands::Count {
+            input,
+            per_line,
+            format,
+            context,
+            ignore_config,
+            config_path,
+        } => {
+            let config = if !ignore_config {
+                config::load_config(config_path.as_deref(), context.as_deref())?
+            } else {
+                Config::default()
+            };
+            let tokenizer = resolve_tokenizer(cli.tokenizer.as_deref(), cli.estimate, &config)?;
+            let tokenizer = tokenizer.as_ref();
+
+            let text = if input.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(&input)?
+            };
+
+            match format.as_str() {
+                "plain" => print!("{}", tokens::format_text_count(&text, per_line, tokenizer)),
+                "json" => println!(
+                    "{}",
+                    tokens::format_text_count_json(&text, per_line, tokenizer)
+                ),
+                _ => unreachable!("Invalid format option"),
+            }
+            Ok(())
+        }
+
+        Commands::Unmerge {
+            input,
+            target,
+            format,
+            dry_run,
+            strip_root,
+        } => {
+            let merged = if input.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(&input)?
+            };
+            let mut files = unmerge::parse(&merged, &format)?;
+            if strip_root {
+                if let Some(root) = unmerge::strip_common_root(&mut files) {
+                    eprintln!("Restoring files relative to {}", root.display());
+                }
+            }
+
+            if dry_run {
+                print!("{}", unmerge::diff_files(&files, &target)?);
+            } else {
+                let written = unmerge::write_files(&files, &target)?;
+                println!("Wrote {} files to {}", written.len(), target.display());
+            }
             Ok(())
         }
 
@@ -232,11 +797,301 @@ contexts:
     Ok(())
 }
 
-fn merge_patterns(cli_patterns: &[String], config_patterns: &[String]) -> Vec<String> {
+/// Process files and stream every file returned by `select` as a JSON Lines record.
+///
+/// `select` may transform a file before it is written, or drop it by returning `None`.
+/// Files that could not be read are always written, with their `error`, and
+/// are returned once the stream is finished.
+fn stream_jsonl(
+    files: &[String],
+    cache: Option<&dyn Cache>,
+    tokenizer: &dyn Tokenizer,
+    out: Box<dyn Write + Send>,
+    include_content: bool,
+    select: impl Fn(FileData) -> Option<FileData> + Sync,
+) -> Result<Vec<FileData>> {
+    let writer = Mutex::new(format::JsonlWriter::new(out, include_content, tokenizer));
+    let errors = Mutex::new(Vec::new());
+    file::process_files_with(files, cache, tokenizer, &|fd| {
+        let selected = if fd.error.is_some() {
+            if let Ok(mut errors) = errors.lock() {
+                errors.push(fd.clone());
+            }
+            Some(fd.clone())
+        } else {
+            select(fd.clone())
+        };
+        if let Some(fd) = selected {
+            if let Ok(mut writer) = writer.lock() {
+                writer.write_file(&fd);
+            }
+        }
+    });
+
+    writer
+        .into_inner()
+        .map_err(|_| Error::Config("Output error: JSON Lines writer poisoned".to_string()))?
+        .finish()
+        .map_err(output_error)?;
+    Ok(errors.into_inner().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// List the files that could not be read on stderr, and return them.
+fn report_read_errors(files: &[FileData]) -> Vec<FileData> {
+    let errors: Vec<FileData> = files
+        .iter()
+        .filter(|f| f.error.is_some())
+        .cloned()
+        .collect();
+    if !errors.is_empty() {
+        eprintln!("Failed to read {} files:", errors.len());
+        for file in &errors {
+            eprintln!(
+                "  {}: {}",
+                file.path,
+                file.error.as_deref().unwrap_or_default()
+            );
+        }
+    }
+    errors
+}
+
+/// Add the files that could not be read to a JSON report, as an `errors` array.
+fn with_read_errors(json: String, errors: &[FileData]) -> Result<String> {
+    if errors.is_empty() {
+        return Ok(json);
+    }
+    let mut value: serde_json::Value = serde_json::from_str(&json)?;
+    value["errors"] = format::errors_json(errors);
+    Ok(value.to_string())
+}
+
+/// Find the files to process: the paths read from stdin, or the files under
+/// `path` matching the CLI patterns, or the context's when none are given.
+///
+/// With `report_excluded`, the files and directories that discovery leaves
+/// out are returned too, with the rule that excluded them.
+fn find_candidates(
+    path: &Path,
+    filter_patterns: &[String],
+    ignores: &[String],
+    config: &Config,
+    input: bool,
+    report_excluded: bool,
+) -> Result<(Vec<String>, Vec<Exclusion>)> {
+    if input || finder::has_stdin_pipe() {
+        return Ok((finder::read_from_stdin()?, Vec::new()));
+    }
+
+    let (mut include, include_origin) = merge_patterns(filter_patterns, &config.filters, "filters");
+    if include.is_empty() {
+        include.push("**".to_string());
+    }
+    let (ignore, ignore_origin) = merge_patterns(ignores, &config.ignores, "ignores");
+    if !report_excluded {
+        return Ok((finder::find_files(path, &include, &ignore)?, Vec::new()));
+    }
+    exclusion::find_files_explained(
+        path,
+        PatternSource {
+            patterns: &include,
+            origin: &include_origin,
+        },
+        PatternSource {
+            patterns: &ignore,
+            origin: &ignore_origin,
+        },
+    )
+}
+
+/// Print the token boundaries and most expensive lines of a single file.
+///
+/// Tokens get alternating background colors when stdout is a terminal and
+/// `NO_COLOR` is not set.
+fn explain_file(
+    path: &Path,
+    format: &str,
+    max_lines: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Result<()> {
+    if !matches!(format, "plain" | "json") {
+        return Err(Error::Config(format!(
+            "--explain is not supported by the {} format",
+            format
+        )));
+    }
+    let text = std::fs::read_to_string(path)?;
+    let tokens = tokenizer.tokenize(&text).ok_or_else(|| {
+        Error::Config("--explain needs a tokenizer and cannot be used with --estimate".to_string())
+    })?;
+    let path = path.display().to_string();
+
+    if format == "json" {
+        println!(
+            "{}",
+            tokens::format_explain_json(&path, &text, &tokens, max_lines, tokenizer)
+        );
+    } else {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        print!(
+            "{}",
+            tokens::format_explain(&path, &text, &tokens, max_lines, tokenizer, color)
+        );
+    }
+    Ok(())
+}
+
+/// Cut a file over `max_budget` down to it with `--truncate`, and report the
+/// cut to stderr.
+fn truncate_oversized(
+    file: FileData,
+    max_budget: usize,
+    truncation: Truncation,
+    tokenizer: &dyn Tokenizer,
+) -> FileData {
+    let tokens = file.tokens;
+    let file = truncate::truncate_file(file, max_budget, truncation, tokenizer);
+    if file.tokens != tokens {
+        eprintln!(
+            "Truncated {} from {} to {} tokens ({})",
+            file.path,
+            tokens,
+            file.tokens,
+            truncation.as_str()
+        );
+    } else if tokens > max_budget {
+        eprintln!(
+            "Cannot truncate {} to {} tokens ({}): no whole line fits next to the omission marker, dropping it",
+            file.path,
+            max_budget,
+            truncation.as_str()
+        );
+    }
+    file
+}
+
+/// Count the rendered cost of every file, see [`format::rendered_file_cost`].
+fn rendered_costs(
+    files: &[FileData],
+    format_type: &str,
+    tokenizer: &dyn Tokenizer,
+) -> Result<Vec<usize>> {
+    files
+        .par_iter()
+        .map(|fd| format::rendered_file_cost(fd, format_type, tokenizer))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(output_error)
+}
+
+/// Keep the files that fit `--total-budget`, given the cost of each file,
+/// preferring higher priorities, and report the dropped ones to stderr.
+fn apply_total_budget(
+    files: Vec<FileData>,
+    costs: &[usize],
+    total_budget: Option<usize>,
+    strategy: &str,
+    priorities: &Priorities,
+) -> Result<Vec<FileData>> {
+    let Some(budget) = total_budget else {
+        return Ok(files);
+    };
+    let strategy = Strategy::from_name(strategy)?;
+    let weights = priorities.weights(&files);
+    let selection = selection::select_files(files, costs, &weights, budget, strategy);
+    eprint!("{}", selection.format_report(budget, strategy));
+    Ok(selection.kept)
+}
+
+/// [`apply_total_budget`] with the token count of each file as its cost.
+fn apply_total_budget_by_tokens(
+    files: Vec<FileData>,
+    total_budget: Option<usize>,
+    strategy: &str,
+    priorities: &Priorities,
+) -> Result<Vec<FileData>> {
+    let costs: Vec<usize> = files.iter().map(|f| f.tokens).collect();
+    apply_total_budget(files, &costs, total_budget, strategy, priorities)
+}
+
+/// Print the token count of the rendered merge output to stderr.
+fn report_rendered(tokens: usize, label: &str, tokenizer: &dyn Tokenizer) {
+    let approx = if tokenizer.is_estimate() { "~" } else { "" };
+    eprintln!("Rendered output: {}{} tokens ({})", approx, tokens, label);
+}
+
+/// Check `tokens` of rendered output against the `--fit` models and print the
+/// report to stderr. With `strict`, overflowing any of them is an error and
+/// nothing is written.
+fn check_fit(
+    models: &[String],
+    tokens: usize,
+    reserve: usize,
+    strict: bool,
+    format: &str,
+    tokenizer: &dyn Tokenizer,
+) -> Result<()> {
+    if models.is_empty() {
+        return Ok(());
+    }
+    let report = FitReport::new(models, tokens, reserve)?;
+    eprint!("{}", report.format(format, tokenizer.is_estimate()));
+    if strict {
+        enforce_fit(&report)?;
+    }
+    Ok(())
+}
+
+fn enforce_fit(report: &FitReport) -> Result<()> {
+    let overflows = report.overflows();
+    if overflows.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Overflow(format!(
+        "the output does not fit {}",
+        overflows
+            .iter()
+            .map(|fit| fit.model.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
+
+/// Load the tokenizer: `--estimate` selects the heuristic estimator, the
+/// `--tokenizer` option wins over the context's `tokenizer` key, and
+/// cl100k_base is used when none of them is set.
+fn resolve_tokenizer(
+    cli_tokenizer: Option<&str>,
+    estimate: bool,
+    config: &Config,
+) -> Result<Box<dyn Tokenizer>> {
+    if estimate {
+        return Ok(Box::new(Estimator));
+    }
+    match cli_tokenizer.or(config.tokenizer.as_deref()) {
+        Some(name) => tokenizer::load(name),
+        None => Ok(Box::new(Encoding::default())),
+    }
+}
+
+fn report_title(path: &Path) -> String {
+    format!("codemerge report: {}", path.display())
+}
+
+fn output_error(e: anyhow::Error) -> Error {
+    Error::Config(format!("Output error: {}", e))
+}
+
+/// Take the patterns of option `name` from the command line, or else from the
+/// config context, along with where they came from for the exclusion report.
+fn merge_patterns(
+    cli_patterns: &[String],
+    config_patterns: &[String],
+    name: &str,
+) -> (Vec<String>, String) {
     if cli_patterns.is_empty() {
-        config_patterns.to_vec()
+        (config_patterns.to_vec(), format!("config {}", name))
     } else {
-        cli_patterns.to_vec()
+        (cli_patterns.to_vec(), format!("--{}", name))
     }
 }
 
@@ -246,8 +1101,50 @@ fn print_info(info: &Info) {
     let mb = (info.allocated as f64) / (BYTES_IN_MB as f64);
     println!("Cache Info: ");
 
-    println!("");
+    println!();
 
     println!("Total records: {:?}", info.records);
     println!("Total size: {:?} mb", mb.round());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_exclusions_name_the_config_glob() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        std::fs::write(root.join("main.rs"), "fn main() {}")?;
+        std::fs::write(root.join("notes.txt"), "notes")?;
+        let config = Config {
+            filters: vec!["**/*.rs".to_string()],
+            ..Config::default()
+        };
+
+        let (include, origin) = merge_patterns(&[], &config.filters, "filters");
+        assert_eq!(origin, "config filters");
+        let (_, excluded) = exclusion::find_files_explained(
+            root,
+            PatternSource {
+                patterns: &include,
+                origin: &origin,
+            },
+            PatternSource {
+                patterns: &[],
+                origin: "config ignores",
+            },
+        )?;
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(
+            excluded[0].reason,
+            "not matched by any config filters pattern"
+        );
+
+        let cli = vec!["**/*.txt".to_string()];
+        let (include, origin) = merge_patterns(&cli, &config.filters, "filters");
+        assert_eq!((include, origin.as_str()), (cli, "--filters"));
+        Ok(())
+    }
+}
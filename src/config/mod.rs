@@ -1,13 +1,19 @@
 // src/config/mod.rs
 
 use crate::error::{Error, Result};
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
     pub filters: Vec<String>,
     pub ignores: Vec<String>,
+    pub template: Option<PathBuf>,
+    pub with_tree: bool,
+    pub with_stats: bool,
+    pub tokenizer: Option<String>,
+    pub pricing: Option<PathBuf>,
+    pub priorities: Vec<(String, f64)>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,12 +29,47 @@ struct Context {
     filters: Vec<String>,
     #[serde(default)]
     ignores: Vec<String>,
+    #[serde(default)]
+    template: Option<PathBuf>,
+    #[serde(default)]
+    with_tree: bool,
+    #[serde(default)]
+    with_stats: bool,
+    #[serde(default)]
+    tokenizer: Option<String>,
+    #[serde(default)]
+    pricing: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_priorities")]
+    priorities: Vec<(String, f64)>,
 }
 
 fn default_filters() -> Vec<String> {
     vec!["**".to_string()]
 }
 
+/// Read the `priorities` mapping of glob patterns to weights, keeping the
+/// order the patterns are listed in.
+fn deserialize_priorities<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<(String, f64)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mapping = serde_yaml::Mapping::deserialize(deserializer)?;
+    mapping
+        .into_iter()
+        .map(|(pattern, weight)| {
+            let pattern = pattern
+                .as_str()
+                .ok_or_else(|| serde::de::Error::custom("priority patterns must be strings"))?;
+            let weight = weight.as_f64().ok_or_else(|| {
+                serde::de::Error::custom(format!("priority of '{}' must be a number", pattern))
+            })?;
+            Ok((pattern.to_string(), weight))
+        })
+        .collect()
+}
+
 pub fn load_config(config_path: Option<&Path>, context_name: Option<&str>) -> Result<Config> {
     let config_path = config_path.unwrap_or_else(|| Path::new(".codemerge.yaml"));
 
@@ -56,5 +97,11 @@ pub fn load_config(config_path: Option<&Path>, context_name: Option<&str>) -> Re
     Ok(Config {
         filters: context.filters.clone(),
         ignores: context.ignores.clone(),
+        template: context.template.clone(),
+        with_tree: context.with_tree,
+        with_stats: context.with_stats,
+        tokenizer: context.tokenizer.clone(),
+        pricing: context.pricing.clone(),
+        priorities: context.priorities.clone(),
     })
 }
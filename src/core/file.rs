@@ -4,7 +4,7 @@
 //! counting their tokens, and interacting with the cache to speed up
 //! subsequent processing.
 
-use super::tokens::count_tokens;
+use super::tokenizer::Tokenizer;
 use crate::cache::Cache;
 use crate::error::Result;
 use memmap2::MmapOptions;
@@ -33,19 +33,29 @@ impl FileData {
     /// Create a new `FileData` instance, calculating tokens from the provided content.
     ///
     /// If the content is empty or contains only whitespace, the token count will be set to 0.
+    /// If the tokenizer fails to encode the content, the file carries the error instead.
     ///
     /// # Arguments
     ///
     /// * `path` - The path representing the file.
     /// * `content` - The file content.
-    pub fn new(path: impl Into<String>, content: impl Into<String>) -> Self {
+    /// * `tokenizer` - The tokenizer used to count the tokens.
+    pub fn new(
+        path: impl Into<String>,
+        content: impl Into<String>,
+        tokenizer: &dyn Tokenizer,
+    ) -> Self {
+        let path = path.into();
         let content = content.into();
-        let tokens = count_tokens(&content);
+        let tokens = match tokenizer.try_count_file(&path, &content) {
+            Ok(tokens) => tokens,
+            Err(e) => return Self::with_error(path, format!("Failed to count tokens: {}", e)),
+        };
 
         // If content is empty or only whitespace, return with 0 tokens
         if content.trim().is_empty() {
             return Self {
-                path: path.into(),
+                path,
                 content: String::new(),
                 tokens: 0,
                 error: None,
@@ -53,7 +63,7 @@ impl FileData {
         }
 
         Self {
-            path: path.into(),
+            path,
             tokens,
             content,
             error: None,
@@ -76,6 +86,26 @@ impl FileData {
             error: Some(error.into()),
         }
     }
+
+    /// Prefix every line of the content with its 1-based line number and
+    /// recount the tokens of the numbered content.
+    ///
+    /// The gutter is padded to the width of the largest line number, so all
+    /// lines of a file stay aligned. Files without content are returned unchanged.
+    pub fn with_line_numbers(self, tokenizer: &dyn Tokenizer) -> Self {
+        if self.content.is_empty() {
+            return self;
+        }
+
+        let line_count = self.content.lines().count();
+        let width = line_count.to_string().len();
+        let mut numbered = String::with_capacity(self.content.len() + line_count * (width + 3));
+        for (i, line) in self.content.split_inclusive('\n').enumerate() {
+            numbered.push_str(&format!("{:>width$} | {}", i + 1, line, width = width));
+        }
+
+        Self::new(self.path, numbered, tokenizer)
+    }
 }
 
 /// Read the content of a file from disk into a `FileData` instance.
@@ -86,11 +116,12 @@ impl FileData {
 /// # Arguments
 ///
 /// * `path` - A reference to the file path to read.
+/// * `tokenizer` - The tokenizer used to count the tokens.
 ///
 /// # Returns
 ///
 /// * `Result<FileData>` - Contains the processed file data, or an error if reading fails.
-pub fn read_file(path: &Path) -> Result<FileData> {
+pub fn read_file(path: &Path, tokenizer: &dyn Tokenizer) -> Result<FileData> {
     let file = File::open(path)?;
     let metadata = file.metadata()?;
 
@@ -99,13 +130,18 @@ pub fn read_file(path: &Path) -> Result<FileData> {
         return Ok(FileData::new(
             path.to_string_lossy().to_string(),
             String::new(),
+            tokenizer,
         ));
     }
 
     // For small files (< 8KB), use regular read
     if metadata.len() < 8192 {
         let content = std::fs::read_to_string(path)?;
-        return Ok(FileData::new(path.to_string_lossy().to_string(), content));
+        return Ok(FileData::new(
+            path.to_string_lossy().to_string(),
+            content,
+            tokenizer,
+        ));
     }
 
     // For larger files, use memory mapping
@@ -115,6 +151,7 @@ pub fn read_file(path: &Path) -> Result<FileData> {
         Ok(content) => Ok(FileData::new(
             path.to_string_lossy().to_string(),
             content.to_string(),
+            tokenizer,
         )),
         Err(e) => Ok(FileData::with_error(
             path.to_string_lossy().to_string(),
@@ -130,11 +167,41 @@ pub fn read_file(path: &Path) -> Result<FileData> {
 ///
 /// * `paths` - A slice of file paths to process.
 /// * `cache` - An optional reference to a `Cache` implementation to speed up processing.
+/// * `tokenizer` - The tokenizer used to count the tokens.
 ///
 /// # Returns
 ///
 /// * `Vec<FileData>` - A list of successfully read and processed `FileData` objects.
-pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<FileData> {
+pub fn process_files(
+    paths: &[String],
+    cache: Option<&dyn Cache>,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<FileData> {
+    process_files_with(paths, cache, tokenizer, &|_| {})
+}
+
+/// Process a list of file paths like [`process_files`], invoking `on_file` for
+/// each file as soon as its data is available.
+///
+/// Cached files are reported first, followed by freshly read files in completion
+/// order, so callers can stream results without waiting for the whole batch.
+///
+/// # Arguments
+///
+/// * `paths` - A slice of file paths to process.
+/// * `cache` - An optional reference to a `Cache` implementation to speed up processing.
+/// * `tokenizer` - The tokenizer used to count the tokens.
+/// * `on_file` - A callback invoked once per processed file, possibly from several threads.
+///
+/// # Returns
+///
+/// * `Vec<FileData>` - A list of processed `FileData` objects in the order of `paths`.
+pub fn process_files_with(
+    paths: &[String],
+    cache: Option<&dyn Cache>,
+    tokenizer: &dyn Tokenizer,
+    on_file: &(dyn Fn(&FileData) + Sync),
+) -> Vec<FileData> {
     if paths.is_empty() {
         return Vec::new();
     }
@@ -157,7 +224,7 @@ pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<Fi
             .iter()
             .map(|(p, m)| (p.as_str(), *m))
             .collect();
-        cache.get_file_data_batch(&query_paths)
+        cache.get_file_data_batch(&query_paths, tokenizer)
     } else {
         vec![None; paths.len()]
     };
@@ -166,12 +233,10 @@ pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<Fi
     let mut results = vec![None; paths.len()];
     let mut misses = Vec::new();
 
-    for (i, (cached, (path, mtime))) in cached_results
-        .into_iter()
-        .zip(paths_with_mtime.into_iter())
-        .enumerate()
+    for (i, (cached, (path, mtime))) in cached_results.into_iter().zip(paths_with_mtime).enumerate()
     {
         if let Some(data) = cached {
+            on_file(&data);
             results[i] = Some(data);
         } else {
             misses.push((i, path, mtime));
@@ -186,9 +251,10 @@ pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<Fi
     let processed_misses: Vec<(usize, FileData, SystemTime)> = misses
         .into_par_iter()
         .map(|(i, path, mtime)| {
-            let file_data = read_file(Path::new(path)).unwrap_or_else(|e| {
+            let file_data = read_file(Path::new(path), tokenizer).unwrap_or_else(|e| {
                 FileData::with_error(path, format!("Failed to read file: {}", e))
             });
+            on_file(&file_data);
             (i, file_data, mtime)
         })
         .collect();
@@ -199,7 +265,7 @@ pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<Fi
             .iter()
             .map(|(_, data, mtime)| (data.clone(), *mtime))
             .collect();
-        let _ = cache.store_file_data_batch(&store_batch);
+        let _ = cache.store_file_data_batch(&store_batch, tokenizer);
     }
 
     // 6. Merge results
@@ -209,3 +275,31 @@ pub fn process_files(paths: &[String], cache: Option<&Box<dyn Cache>>) -> Vec<Fi
 
     results.into_iter().flatten().collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+    use crate::core::tokens::count_tokens;
+
+    #[test]
+    fn test_with_line_numbers() {
+        let content: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let tokenizer = &Encoding::default();
+        let numbered =
+            FileData::new("a.txt", content.clone(), tokenizer).with_line_numbers(tokenizer);
+
+        assert!(numbered.content.starts_with(" 1 | line 1\n 2 | line 2\n"));
+        assert!(numbered.content.ends_with("10 | line 10\n"));
+        assert_eq!(numbered.tokens, count_tokens(&numbered.content, tokenizer));
+        assert!(numbered.tokens > count_tokens(&content, tokenizer));
+    }
+
+    #[test]
+    fn test_with_line_numbers_without_trailing_newline() {
+        let tokenizer = &Encoding::default();
+        let numbered =
+            FileData::new("a.txt", "first\nsecond", tokenizer).with_line_numbers(tokenizer);
+        assert_eq!(numbered.content, "1 | first\n2 | second");
+    }
+}
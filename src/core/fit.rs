@@ -0,0 +1,219 @@
+//! Context window fit checks.
+//!
+//! Compares the token count of a rendered prompt, plus the tokens reserved for
+//! the response, against the context window of one or more target models. A
+//! built-in table covers common hosted models; other models are given as
+//! `name=tokens`.
+
+use crate::error::{Error, Result};
+use serde_json::json;
+
+/// Built-in context windows in tokens.
+const BUILTIN_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4o-mini", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4.1-mini", 1_047_576),
+    ("gpt-4.1-nano", 1_047_576),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o3-mini", 200_000),
+    ("o4-mini", 200_000),
+    ("claude-opus-4", 200_000),
+    ("claude-sonnet-4", 200_000),
+    ("claude-3-7-sonnet", 200_000),
+    ("claude-3-5-sonnet", 200_000),
+    ("claude-3-5-haiku", 200_000),
+    ("claude-3-opus", 200_000),
+    ("gemini-2.5-pro", 1_048_576),
+    ("gemini-2.5-flash", 1_048_576),
+    ("gemini-2.0-flash", 1_048_576),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-1.5-flash", 1_048_576),
+    ("mistral-large", 131_072),
+    ("mistral-small", 131_072),
+];
+
+/// The fit of a prompt in one model's context window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit {
+    /// The model name.
+    pub model: String,
+    /// The model's context window in tokens.
+    pub window: usize,
+    /// The tokens left once the prompt and the reserved response are in;
+    /// negative when the window overflows.
+    pub headroom: i64,
+}
+
+/// The fit of a prompt in the context windows of the target models.
+#[derive(Debug, Clone)]
+pub struct FitReport {
+    /// The tokens of the rendered prompt, formatting included.
+    pub tokens: usize,
+    /// The tokens reserved for the response.
+    pub reserved: usize,
+    /// One entry per target model, in the order they were given.
+    pub fits: Vec<Fit>,
+}
+
+/// Look up the context window of a model.
+///
+/// # Arguments
+///
+/// * `spec` - A model name from the built-in table (compared case-insensitively),
+///   or `name=tokens` for any other model.
+///
+/// # Returns
+///
+/// * `Result<(String, usize)>` - The model name and its context window.
+pub fn context_window(spec: &str) -> Result<(String, usize)> {
+    if let Some((model, tokens)) = spec.split_once('=') {
+        let tokens = tokens
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| Error::Config(format!("Invalid context window in '{}'", spec)))?;
+        return Ok((model.trim().to_string(), tokens));
+    }
+
+    BUILTIN_WINDOWS
+        .iter()
+        .find(|(model, _)| model.eq_ignore_ascii_case(spec))
+        .map(|(model, tokens)| (model.to_string(), *tokens))
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "Unknown context window for model '{}'. Known models: {}. Use MODEL=TOKENS for other models",
+                spec,
+                BUILTIN_WINDOWS
+                    .iter()
+                    .map(|(model, _)| *model)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+}
+
+impl FitReport {
+    /// Check a prompt of `tokens` tokens, plus `reserved` response tokens,
+    /// against every model in `models`.
+    pub fn new(models: &[String], tokens: usize, reserved: usize) -> Result<Self> {
+        let fits = models
+            .iter()
+            .map(|spec| {
+                let (model, window) = context_window(spec)?;
+                Ok(Fit {
+                    model,
+                    window,
+                    headroom: window as i64 - tokens as i64 - reserved as i64,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            tokens,
+            reserved,
+            fits,
+        })
+    }
+
+    /// The models whose context window the prompt overflows.
+    pub fn overflows(&self) -> Vec<&Fit> {
+        self.fits.iter().filter(|fit| fit.headroom < 0).collect()
+    }
+
+    /// Format the report as plain text.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The output format the prompt was rendered in.
+    /// * `estimated` - Whether the token counts are estimates, marked with `~`.
+    pub fn format(&self, format: &str, estimated: bool) -> String {
+        let approx = if estimated { "~" } else { "" };
+        let mut result = format!(
+            "Context window fit: {}{} tokens ({} format)",
+            approx, self.tokens, format
+        );
+        if self.reserved > 0 {
+            result.push_str(&format!(" + {} reserved for the response", self.reserved));
+        }
+        result.push('\n');
+
+        let max_model_len = self.fits.iter().map(|f| f.model.len()).max().unwrap_or(0);
+        for fit in &self.fits {
+            let status = if fit.headroom >= 0 {
+                format!("fits, {}{} tokens of headroom", approx, fit.headroom)
+            } else {
+                format!("OVERFLOW by {}{} tokens", approx, -fit.headroom)
+            };
+            result.push_str(&format!(
+                "  {:<width$} │ {:>9} │ {}\n",
+                fit.model,
+                fit.window,
+                status,
+                width = max_model_len
+            ));
+        }
+        result
+    }
+
+    /// Build the report as a JSON value.
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "tokens": self.tokens,
+            "reserved": self.reserved,
+            "models": self
+                .fits
+                .iter()
+                .map(|fit| {
+                    json!({
+                        "model": fit.model,
+                        "context_window": fit.window,
+                        "headroom": fit.headroom,
+                        "fits": fit.headroom >= 0,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(
+            context_window("GPT-4o").unwrap(),
+            ("gpt-4o".to_string(), 128_000)
+        );
+        assert_eq!(
+            context_window("my-llama=32768").unwrap(),
+            ("my-llama".to_string(), 32_768)
+        );
+        assert!(context_window("my-llama=lots").is_err());
+        assert!(context_window("unknown-model").is_err());
+    }
+
+    #[test]
+    fn test_fit_report() {
+        let models = vec!["gpt-4".to_string(), "gpt-4o".to_string()];
+        let report = FitReport::new(&models, 8_000, 1_000).unwrap();
+        assert_eq!(report.fits[0].headroom, -808);
+        assert_eq!(report.fits[1].headroom, 119_000);
+        assert_eq!(report.overflows().len(), 1);
+        assert_eq!(report.overflows()[0].model, "gpt-4");
+
+        let text = report.format("markdown", false);
+        assert!(text.contains("8000 tokens (markdown format) + 1000 reserved"));
+        assert!(text.contains("OVERFLOW by 808 tokens"));
+        assert!(text.contains("fits, 119000 tokens of headroom"));
+
+        let json = report.to_json();
+        assert_eq!(json["models"][0]["fits"], false);
+        assert_eq!(json["models"][1]["context_window"], 128_000);
+    }
+}
@@ -0,0 +1,346 @@
+//! Token breakdown by file extension, language or directory.
+//!
+//! Aggregates processed files into groups and reports, for each group, the
+//! number of files, the total tokens, the share of all tokens and the largest
+//! file, in plain text or JSON format.
+
+use super::file::FileData;
+use super::fit::FitReport;
+use super::tokenizer::Tokenizer;
+use crate::error::{Error, Result};
+use crate::utils::finder;
+use crate::utils::language::detect_language;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How files are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// By file extension, e.g. `.rs`.
+    Extension,
+    /// By detected language, e.g. `rust`.
+    Language,
+    /// By the leading directories of the path, up to the given depth.
+    Directory(usize),
+}
+
+/// The aggregated token counts of one group of files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    /// The extension, language or directory shared by the files.
+    pub name: String,
+    /// The number of files in the group.
+    pub files: usize,
+    /// The sum of tokens of the files in the group.
+    pub tokens: usize,
+    /// The path and token count of the largest file in the group.
+    pub largest: (String, usize),
+}
+
+impl GroupBy {
+    /// Parse a `--group-by` value: `ext`, `lang` or `dir`, the latter grouping
+    /// directories down to `depth` levels.
+    pub fn from_name(name: &str, depth: usize) -> Result<Self> {
+        match name {
+            "ext" => Ok(Self::Extension),
+            "lang" => Ok(Self::Language),
+            "dir" if depth == 0 => Err(Error::Config("--depth must be at least 1".to_string())),
+            "dir" => Ok(Self::Directory(depth)),
+            _ => Err(Error::Config(format!("Unknown grouping: {}", name))),
+        }
+    }
+
+    /// The short name of the grouping, as accepted by [`GroupBy::from_name`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Extension => "ext",
+            Self::Language => "lang",
+            Self::Directory(_) => "dir",
+        }
+    }
+
+    /// The name of the group a file belongs to, given its path relative to
+    /// the scan root.
+    pub fn key(&self, path: &str) -> String {
+        match self {
+            Self::Extension => Path::new(path)
+                .extension()
+                .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+                .unwrap_or_else(|| "(none)".to_string()),
+            Self::Language => detect_language(path).unwrap_or("(other)").to_string(),
+            Self::Directory(depth) => {
+                let components: Vec<&str> = path
+                    .split('/')
+                    .filter(|c| !c.is_empty() && *c != ".")
+                    .collect();
+                let directories = &components[..components.len().saturating_sub(1)];
+                if directories.is_empty() {
+                    ".".to_string()
+                } else {
+                    directories[..directories.len().min(*depth)].join("/") + "/"
+                }
+            }
+        }
+    }
+}
+
+/// Aggregate files into groups, largest total first.
+///
+/// # Arguments
+///
+/// * `files` - A slice of `FileData` representing processed files.
+/// * `group_by` - How files are grouped.
+/// * `root` - The scan root, which directories are named relative to.
+///
+/// # Returns
+///
+/// * `Vec<Group>` - One entry per group, sorted by total tokens, then name.
+pub fn group_files(files: &[FileData], group_by: GroupBy, root: &Path) -> Vec<Group> {
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    for file in files {
+        let name = group_by.key(finder::relative_path(&file.path, root));
+        let group = groups.entry(name.clone()).or_insert_with(|| Group {
+            name,
+            files: 0,
+            tokens: 0,
+            largest: (file.path.clone(), file.tokens),
+        });
+        group.files += 1;
+        group.tokens += file.tokens;
+        if file.tokens > group.largest.1 {
+            group.largest = (file.path.clone(), file.tokens);
+        }
+    }
+
+    let mut groups: Vec<Group> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
+
+fn share(tokens: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        tokens as f64 * 100.0 / total as f64
+    }
+}
+
+/// Format an ASCII-based board of the groups with the highest token counts.
+///
+/// # Arguments
+///
+/// * `files` - A slice of `FileData` representing processed files.
+/// * `group_by` - How files are grouped.
+/// * `root` - The scan root, which directories are named relative to.
+/// * `max_display` - The maximum number of groups to show in the board.
+/// * `tokenizer` - The tokenizer the token counts were computed with; estimated
+///   counts are marked with `~`.
+///
+/// # Returns
+///
+/// * `String` - A formatted string ready to be printed to standard output.
+pub fn format_group_board(
+    files: &[FileData],
+    group_by: GroupBy,
+    root: &Path,
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+) -> String {
+    let groups = group_files(files, group_by, root);
+    let total: usize = files.iter().map(|f| f.tokens).sum();
+    let approx = if tokenizer.is_estimate() { "~" } else { "" };
+
+    let rows: Vec<String> = groups
+        .iter()
+        .take(max_display)
+        .map(|group| {
+            format!(
+                "{:>6} files │ {:>8} tokens │ {:>5.1}% │ {} ({}{})",
+                group.files,
+                format!("{}{}", approx, group.tokens),
+                share(group.tokens, total),
+                group.largest.0,
+                approx,
+                group.largest.1
+            )
+        })
+        .collect();
+    let max_name_len = groups
+        .iter()
+        .take(max_display)
+        .map(|g| g.name.len())
+        .max()
+        .unwrap_or(0);
+    let width = max_name_len
+        + 3
+        + rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(17);
+
+    let mut result = format!(
+        "\nToken Statistics by {}{}:\n",
+        match group_by {
+            GroupBy::Extension => "extension".to_string(),
+            GroupBy::Language => "language".to_string(),
+            GroupBy::Directory(depth) => format!("directory (depth {})", depth),
+        },
+        if tokenizer.is_estimate() {
+            " (estimated)"
+        } else {
+            ""
+        }
+    );
+    result.push_str(&"─".repeat(width));
+    result.push('\n');
+    for (group, row) in groups.iter().zip(&rows) {
+        result.push_str(&format!(
+            "{:<width$} │ {}\n",
+            group.name,
+            row,
+            width = max_name_len
+        ));
+    }
+    result.push_str(&"─".repeat(width));
+    result.push('\n');
+    result.push_str(&format!(
+        "Total: {} groups, {} files, {}{} tokens\n",
+        groups.len(),
+        files.len(),
+        approx,
+        total
+    ));
+    result
+}
+
+/// Format the group statistics as a JSON payload.
+///
+/// # Arguments
+///
+/// * `files` - A slice of `FileData` representing processed files.
+/// * `group_by` - How files are grouped.
+/// * `root` - The scan root, which directories are named relative to.
+/// * `max_display` - The maximum number of groups to include.
+/// * `tokenizer` - The tokenizer the token counts were computed with.
+/// * `fit` - When set, the context window fit of the selection is included.
+///
+/// # Returns
+///
+/// * `String` - A JSON-formatted string representing the group statistics.
+pub fn format_group_json(
+    files: &[FileData],
+    group_by: GroupBy,
+    root: &Path,
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+    fit: Option<&FitReport>,
+) -> String {
+    let total: usize = files.iter().map(|f| f.tokens).sum();
+    let groups: Vec<_> = group_files(files, group_by, root)
+        .into_iter()
+        .take(max_display)
+        .map(|group| {
+            serde_json::json!({
+                "name": group.name,
+                "files": group.files,
+                "tokens": group.tokens,
+                "share": share(group.tokens, total),
+                "largest": {
+                    "path": group.largest.0,
+                    "tokens": group.largest.1,
+                },
+            })
+        })
+        .collect();
+
+    let mut value = serde_json::json!({
+        "tokenizer": tokenizer.name(),
+        "estimated": tokenizer.is_estimate(),
+        "group_by": group_by.as_str(),
+        "total": total,
+        "groups": groups,
+    });
+    if let GroupBy::Directory(depth) = group_by {
+        value["depth"] = serde_json::json!(depth);
+    }
+    if let Some(fit) = fit {
+        value["fit"] = fit.to_json();
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    #[test]
+    fn test_group_keys() {
+        assert_eq!(GroupBy::Extension.key("src/Main.RS"), ".rs");
+        assert_eq!(GroupBy::Extension.key("Makefile"), "(none)");
+        assert_eq!(GroupBy::Language.key("src/lib.rs"), "rust");
+        assert_eq!(GroupBy::Language.key("data.bin"), "(other)");
+        assert_eq!(GroupBy::Directory(1).key("src/core/file.rs"), "src/");
+        assert_eq!(GroupBy::Directory(2).key("src/core/file.rs"), "src/core/");
+        assert_eq!(GroupBy::Directory(3).key("src/core/file.rs"), "src/core/");
+        assert_eq!(GroupBy::Directory(1).key("./README.md"), ".");
+        assert!(GroupBy::from_name("dir", 0).is_err());
+    }
+
+    #[test]
+    fn test_group_files() {
+        let encoding = Encoding::default();
+        let files = vec![
+            FileData::new(
+                "src/main.rs",
+                "fn main() { println!(\"hello\"); }",
+                &encoding,
+            ),
+            FileData::new("src/lib.rs", "pub fn lib() {}", &encoding),
+            FileData::new("docs/guide.md", "# Guide", &encoding),
+        ];
+
+        let groups = group_files(&files, GroupBy::Directory(1), Path::new("."));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "src/");
+        assert_eq!(groups[0].files, 2);
+        assert_eq!(groups[0].tokens, files[0].tokens + files[1].tokens);
+        assert_eq!(groups[0].largest.0, "src/main.rs");
+
+        let board = format_group_board(&files, GroupBy::Language, Path::new("."), 10, &encoding);
+        assert!(board.contains("Token Statistics by language:"));
+        assert!(board.contains("rust"));
+        assert!(board.contains("Total: 2 groups, 3 files"));
+
+        let json: serde_json::Value = serde_json::from_str(&format_group_json(
+            &files,
+            GroupBy::Directory(1),
+            Path::new("."),
+            1,
+            &encoding,
+            None,
+        ))
+        .unwrap();
+        assert_eq!(json["group_by"], "dir");
+        assert_eq!(json["depth"], 1);
+        assert_eq!(json["groups"].as_array().unwrap().len(), 1);
+        assert_eq!(json["groups"][0]["largest"]["path"], "src/main.rs");
+    }
+
+    #[test]
+    fn test_directories_are_relative_to_the_root() {
+        let encoding = Encoding::default();
+        let files = vec![
+            FileData::new("/repo/src/core/file.rs", "fn file() {}", &encoding),
+            FileData::new("/repo/src/core/tree.rs", "fn tree() {}", &encoding),
+            FileData::new("/repo/src/utils/format.rs", "fn format() {}", &encoding),
+            FileData::new("/repo/src/main.rs", "fn main() {}", &encoding),
+        ];
+
+        let groups = group_files(&files, GroupBy::Directory(1), Path::new("/repo/src"));
+        let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["core/", ".", "utils/"]);
+        assert_eq!(groups[0].largest.0, "/repo/src/core/file.rs");
+    }
+}
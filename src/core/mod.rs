@@ -4,5 +4,11 @@
 //! counting tokens, and building directory trees for output.
 
 pub mod file;
+pub mod fit;
+pub mod group;
+pub mod pricing;
+pub mod stats;
+pub mod tokenizer;
 pub mod tokens;
 pub mod tree;
+pub mod truncate;
@@ -0,0 +1,177 @@
+//! Prompt cost estimation.
+//!
+//! Holds a pricing table of input token prices per model, in US dollars per
+//! million tokens. A built-in table covers common hosted models; entries can
+//! be added or overridden from a local YAML file:
+//!
+//! ```yaml
+//! models:
+//!   gpt-4o: 2.50
+//!   my-llama-70b: 0.40
+//! ```
+
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Built-in input prices in USD per million tokens.
+///
+/// Providers change their prices from time to time, so these are defaults
+/// to be overridden with a pricing file rather than a source of truth.
+const BUILTIN_PRICES: &[(&str, f64)] = &[
+    ("gpt-4o", 2.50),
+    ("gpt-4o-mini", 0.15),
+    ("gpt-4.1", 2.00),
+    ("gpt-4.1-mini", 0.40),
+    ("gpt-4.1-nano", 0.10),
+    ("gpt-4-turbo", 10.00),
+    ("gpt-4", 30.00),
+    ("gpt-3.5-turbo", 0.50),
+    ("o1", 15.00),
+    ("o3", 2.00),
+    ("o3-mini", 1.10),
+    ("o4-mini", 1.10),
+    ("claude-opus-4", 15.00),
+    ("claude-sonnet-4", 3.00),
+    ("claude-3-7-sonnet", 3.00),
+    ("claude-3-5-sonnet", 3.00),
+    ("claude-3-5-haiku", 0.80),
+    ("claude-3-opus", 15.00),
+    ("gemini-2.5-pro", 1.25),
+    ("gemini-2.5-flash", 0.30),
+    ("gemini-2.0-flash", 0.10),
+    ("gemini-1.5-pro", 1.25),
+    ("gemini-1.5-flash", 0.075),
+    ("mistral-large", 2.00),
+    ("mistral-small", 0.20),
+];
+
+#[derive(Debug, Deserialize)]
+struct PricingFile {
+    #[serde(default)]
+    models: BTreeMap<String, f64>,
+}
+
+/// Input token prices per model.
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    models: BTreeMap<String, f64>,
+}
+
+/// The input price of one model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Price {
+    /// The model name.
+    pub model: String,
+    /// The price in USD per million input tokens.
+    pub per_million: f64,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        Self {
+            models: BUILTIN_PRICES
+                .iter()
+                .map(|(model, price)| (model.to_string(), *price))
+                .collect(),
+        }
+    }
+}
+
+impl PricingTable {
+    /// Load the built-in table, extended and overridden by the YAML file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read pricing file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut table = Self::default();
+        table.extend(&source)?;
+        Ok(table)
+    }
+
+    /// Add or override prices from the YAML source of a pricing file.
+    pub fn extend(&mut self, source: &str) -> Result<()> {
+        let file: PricingFile = serde_yaml::from_str(source)?;
+        for (model, price) in file.models {
+            if !price.is_finite() || price < 0.0 {
+                return Err(Error::Config(format!(
+                    "Invalid price for model '{}': {}",
+                    model, price
+                )));
+            }
+            self.models.insert(model, price);
+        }
+        Ok(())
+    }
+
+    /// Look up the price of a model.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model name, compared case-insensitively.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Price>` - The price, or an error listing the known models.
+    pub fn price(&self, model: &str) -> Result<Price> {
+        self.models
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(model))
+            .map(|(name, price)| Price {
+                model: name.clone(),
+                per_million: *price,
+            })
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "No price for model '{}'. Known models: {}. Add it to a pricing file with --pricing",
+                    model,
+                    self.models.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            })
+    }
+}
+
+impl Price {
+    /// The cost in USD of sending `tokens` input tokens.
+    pub fn cost(&self, tokens: usize) -> f64 {
+        tokens as f64 * self.per_million / 1_000_000.0
+    }
+}
+
+/// Format a cost in USD with enough precision for single files.
+pub fn format_cost(cost: f64) -> String {
+    format!("${:.4}", cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_price() {
+        let price = PricingTable::default().price("GPT-4o").unwrap();
+        assert_eq!(price.model, "gpt-4o");
+        assert_eq!(price.cost(1_000_000), 2.5);
+        assert_eq!(format_cost(price.cost(1234)), "$0.0031");
+    }
+
+    #[test]
+    fn test_pricing_file_overrides() {
+        let mut table = PricingTable::default();
+        table
+            .extend("models:\n  gpt-4o: 5.0\n  my-llama: 0.2\n")
+            .unwrap();
+        assert_eq!(table.price("gpt-4o").unwrap().per_million, 5.0);
+        assert_eq!(table.price("my-llama").unwrap().per_million, 0.2);
+        assert_eq!(table.price("gpt-4o-mini").unwrap().per_million, 0.15);
+
+        assert!(table.extend("models:\n  broken: -1\n").is_err());
+        let error = table.price("unknown-model").unwrap_err().to_string();
+        assert!(error.contains("my-llama"));
+    }
+}
@@ -0,0 +1,322 @@
+//! Token distribution statistics.
+//!
+//! Summarizes how tokens are spread over files (mean, median, percentiles and
+//! a histogram of file sizes) and how many files each budget rule excludes,
+//! in plain text or JSON format, to help pick budget limits.
+
+use super::file::FileData;
+use super::fit::FitReport;
+use super::tokenizer::Tokenizer;
+use crate::utils::filters::{self, BudgetExclusions};
+
+/// Upper bounds (exclusive) of the histogram buckets, in tokens per file.
+const BUCKET_LIMITS: &[usize] = &[
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000,
+];
+
+/// Width of the longest histogram bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Summary statistics of the token counts of a set of files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    /// The number of files.
+    pub files: usize,
+    /// The sum of tokens of all files.
+    pub total: usize,
+    /// The mean tokens per file.
+    pub mean: f64,
+    /// The median tokens per file.
+    pub median: usize,
+    /// The 90th percentile of tokens per file.
+    pub p90: usize,
+    /// The 99th percentile of tokens per file.
+    pub p99: usize,
+    /// The tokens of the largest file.
+    pub max: usize,
+}
+
+/// A histogram bucket: the files with at least `min` and less than `max` tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    /// The lower bound, inclusive.
+    pub min: usize,
+    /// The upper bound, exclusive; `None` for the last, open-ended bucket.
+    pub max: Option<usize>,
+    /// The number of files in the bucket.
+    pub files: usize,
+}
+
+impl Distribution {
+    /// Compute the distribution of the given token counts.
+    ///
+    /// Returns `None` when there are no counts.
+    pub fn new(tokens: &[usize]) -> Option<Self> {
+        if tokens.is_empty() {
+            return None;
+        }
+        let mut sorted = tokens.to_vec();
+        sorted.sort_unstable();
+        let total: usize = sorted.iter().sum();
+
+        Some(Self {
+            files: sorted.len(),
+            total,
+            mean: total as f64 / sorted.len() as f64,
+            median: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            max: *sorted.last().unwrap(),
+        })
+    }
+}
+
+/// The nearest-rank percentile of sorted, non-empty values.
+fn percentile(sorted: &[usize], p: f64) -> usize {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Count the files per histogram bucket, up to the bucket of the largest file.
+pub fn histogram(tokens: &[usize]) -> Vec<Bucket> {
+    let mut buckets: Vec<Bucket> = std::iter::once(0)
+        .chain(BUCKET_LIMITS.iter().copied())
+        .zip(BUCKET_LIMITS.iter().map(|&limit| Some(limit)).chain([None]))
+        .map(|(min, max)| Bucket { min, max, files: 0 })
+        .collect();
+
+    for &count in tokens {
+        let index = BUCKET_LIMITS
+            .iter()
+            .position(|&limit| count < limit)
+            .unwrap_or(BUCKET_LIMITS.len());
+        buckets[index].files += 1;
+    }
+
+    let last = buckets.iter().rposition(|b| b.files > 0).unwrap_or(0);
+    buckets.truncate(last + 1);
+    buckets
+}
+
+fn non_empty_tokens(files: &[FileData]) -> Vec<usize> {
+    files
+        .iter()
+        .map(|f| f.tokens)
+        .filter(|&tokens| tokens > 0)
+        .collect()
+}
+
+/// Format the token distribution, histogram and budget exclusions as plain text.
+///
+/// # Arguments
+///
+/// * `files` - The processed files, before the budget filters are applied.
+/// * `min` / `max` - The budget limits.
+/// * `limit_high` / `limit_low` - Whether the maximum and minimum budgets are enforced.
+/// * `tokenizer` - The tokenizer the token counts were computed with; estimated
+///   counts are marked with `~`.
+///
+/// # Returns
+///
+/// * `String` - A formatted string ready to be printed to standard output.
+pub fn format_stats_board(
+    files: &[FileData],
+    min: usize,
+    max: usize,
+    limit_high: bool,
+    limit_low: bool,
+    tokenizer: &dyn Tokenizer,
+) -> String {
+    let tokens = non_empty_tokens(files);
+    let approx = if tokenizer.is_estimate() { "~" } else { "" };
+    let mut result = format!(
+        "\nToken Distribution{} ({} files before budget filters):\n",
+        if tokenizer.is_estimate() {
+            " (estimated)"
+        } else {
+            ""
+        },
+        tokens.len()
+    );
+    result.push_str(&"─".repeat(48));
+    result.push('\n');
+
+    if let Some(distribution) = Distribution::new(&tokens) {
+        for (label, value) in [
+            ("Mean", format!("{:.1}", distribution.mean)),
+            ("Median", distribution.median.to_string()),
+            ("p90", distribution.p90.to_string()),
+            ("p99", distribution.p99.to_string()),
+            ("Max", distribution.max.to_string()),
+            ("Total", distribution.total.to_string()),
+        ] {
+            result.push_str(&format!("{:<8} {}{} tokens\n", label, approx, value));
+        }
+
+        result.push_str("\nFiles by size in tokens:\n");
+        let buckets = histogram(&tokens);
+        let largest = buckets.iter().map(|b| b.files).max().unwrap_or(0).max(1);
+        for bucket in &buckets {
+            let range = match bucket.max {
+                Some(limit) => format!("{:>6} - {:<6}", bucket.min, limit - 1),
+                None => format!("{:>6}+{:<8}", bucket.min, ""),
+            };
+            let bar = (bucket.files * BAR_WIDTH).div_ceil(largest);
+            result.push_str(&format!(
+                "{} │ {} {}\n",
+                range,
+                "█".repeat(bar),
+                bucket.files
+            ));
+        }
+    } else {
+        result.push_str("No files with tokens\n");
+    }
+
+    let exclusions = filters::count_exclusions(files, min, max);
+    result.push_str("\nBudget exclusions:\n");
+    result.push_str(&format!(
+        "  unreadable files:         {}\n",
+        exclusions.unreadable
+    ));
+    result.push_str(&format!(
+        "  empty files:              {}\n",
+        exclusions.empty
+    ));
+    result.push_str(&format!(
+        "  below --min-budget {:<6}: {}{}\n",
+        min,
+        exclusions.below_min,
+        if limit_low {
+            ""
+        } else {
+            " (not applied, use -L)"
+        }
+    ));
+    result.push_str(&format!(
+        "  above --max-budget {:<6}: {}{}\n",
+        max,
+        exclusions.above_max,
+        if limit_high {
+            ""
+        } else {
+            " (not applied, use -H)"
+        }
+    ));
+    result
+}
+
+/// Format the token distribution, histogram and budget exclusions as a JSON payload.
+///
+/// Takes the same arguments as [`format_stats_board`], plus the context window
+/// fit of the selection, included when set.
+pub fn format_stats_json(
+    files: &[FileData],
+    min: usize,
+    max: usize,
+    limit_high: bool,
+    limit_low: bool,
+    tokenizer: &dyn Tokenizer,
+    fit: Option<&FitReport>,
+) -> String {
+    let tokens = non_empty_tokens(files);
+    let BudgetExclusions {
+        unreadable,
+        empty,
+        below_min,
+        above_max,
+    } = filters::count_exclusions(files, min, max);
+
+    let mut value = serde_json::json!({
+        "tokenizer": tokenizer.name(),
+        "estimated": tokenizer.is_estimate(),
+        "files": tokens.len(),
+        "histogram": histogram(&tokens)
+            .iter()
+            .map(|b| serde_json::json!({ "min": b.min, "max": b.max, "files": b.files }))
+            .collect::<Vec<_>>(),
+        "excluded": {
+            "unreadable": unreadable,
+            "empty": empty,
+            "below_min": { "min_budget": min, "applied": limit_low, "files": below_min },
+            "above_max": { "max_budget": max, "applied": limit_high, "files": above_max },
+        },
+    });
+    if let Some(distribution) = Distribution::new(&tokens) {
+        value["total"] = serde_json::json!(distribution.total);
+        value["mean"] = serde_json::json!(distribution.mean);
+        value["median"] = serde_json::json!(distribution.median);
+        value["p90"] = serde_json::json!(distribution.p90);
+        value["p99"] = serde_json::json!(distribution.p99);
+        value["max"] = serde_json::json!(distribution.max);
+    }
+    if let Some(fit) = fit {
+        value["fit"] = fit.to_json();
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    #[test]
+    fn test_distribution() {
+        let tokens: Vec<usize> = (1..=100).collect();
+        let distribution = Distribution::new(&tokens).unwrap();
+        assert_eq!(distribution.files, 100);
+        assert_eq!(distribution.total, 5050);
+        assert_eq!(distribution.mean, 50.5);
+        assert_eq!(distribution.median, 50);
+        assert_eq!(distribution.p90, 90);
+        assert_eq!(distribution.p99, 99);
+        assert_eq!(distribution.max, 100);
+        assert_eq!(Distribution::new(&[7]).unwrap().p99, 7);
+        assert!(Distribution::new(&[]).is_none());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let buckets = histogram(&[5, 99, 100, 300, 1_200]);
+        assert_eq!(buckets.len(), 5);
+        assert_eq!(buckets[0].files, 2);
+        assert_eq!(buckets[1].files, 1);
+        assert_eq!(buckets[2].files, 1);
+        assert_eq!(buckets[3].files, 0);
+        assert_eq!(buckets[4].min, 1_000);
+        assert_eq!(buckets[4].max, Some(2_500));
+
+        let open = histogram(&[200_000]);
+        assert_eq!(open.last().unwrap().max, None);
+        assert_eq!(open.last().unwrap().files, 1);
+    }
+
+    #[test]
+    fn test_stats_board() {
+        let encoding = Encoding::default();
+        let files = vec![
+            FileData::new("a.rs", "fn a() {}", &encoding),
+            FileData::new("b.rs", "fn b() { let value = 1 + 2 + 3; }", &encoding),
+            FileData::new("empty.rs", "  \n", &encoding),
+            FileData::with_error("broken.rs", "permission denied"),
+        ];
+
+        let board = format_stats_board(&files, 0, 5, true, false, &encoding);
+        assert!(board.contains("(2 files before budget filters)"));
+        assert!(board.contains("unreadable files:         1"));
+        assert!(board.contains("empty files:              1"));
+        assert!(board.contains("above --max-budget 5     : 1\n"));
+        assert!(board.contains("(not applied, use -L)"));
+
+        let json: serde_json::Value = serde_json::from_str(&format_stats_json(
+            &files, 0, 5, true, false, &encoding, None,
+        ))
+        .unwrap();
+        assert_eq!(json["files"], 2);
+        assert_eq!(json["excluded"]["unreadable"], 1);
+        assert_eq!(json["excluded"]["empty"], 1);
+        assert_eq!(json["excluded"]["above_max"]["files"], 1);
+        assert_eq!(json["max"], files[1].tokens);
+    }
+}
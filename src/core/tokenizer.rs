@@ -0,0 +1,586 @@
+//! Pluggable tokenizers used for token counting.
+//!
+//! Defines the [`Tokenizer`] trait together with its implementations: the
+//! tiktoken BPE [`Encoding`]s bundled with `tiktoken-rs`,
+//! [`HuggingFaceTokenizer`], which loads a local HuggingFace `tokenizer.json`,
+//! and the approximate [`Estimator`]. All of them work fully offline.
+
+use crate::error::{Error, Result};
+use crate::utils::language::detect_language;
+use fnv::FnvHasher;
+use std::fmt;
+use std::hash::Hasher;
+use std::path::Path;
+use std::sync::OnceLock;
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer as TiktokenModel};
+use tiktoken_rs::CoreBPE;
+
+// Each encoding is loaded at most once, on first use.
+static CL100K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+static O200K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+static P50K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+static R50K_BASE: OnceLock<CoreBPE> = OnceLock::new();
+
+/// Something that can split text into tokens and count them.
+pub trait Tokenizer: Send + Sync {
+    /// The name reported in outputs, e.g. `cl100k_base`.
+    fn name(&self) -> &str;
+
+    /// The key cached token counts are stored under.
+    ///
+    /// Counts are only reused for a tokenizer with the same key, so it must
+    /// change whenever the tokenizer would count differently.
+    fn cache_key(&self) -> &str {
+        self.name()
+    }
+
+    /// Count the tokens in `text`.
+    fn count(&self, text: &str) -> usize;
+
+    /// Count the tokens of the file at `path` with the given content.
+    ///
+    /// Tokenizers that depend on the kind of file, like the [`Estimator`],
+    /// use the path; the others count the content only.
+    fn count_file(&self, path: &str, text: &str) -> usize {
+        let _ = path;
+        self.count(text)
+    }
+
+    /// Count the tokens of a file like [`Tokenizer::count_file`], failing
+    /// instead of returning 0 when the text cannot be encoded.
+    fn try_count_file(&self, path: &str, text: &str) -> Result<usize> {
+        Ok(self.count_file(path, text))
+    }
+
+    /// Whether the counts are approximations rather than real token counts.
+    fn is_estimate(&self) -> bool {
+        false
+    }
+
+    /// Split `text` into its tokens, for showing token boundaries.
+    ///
+    /// The pieces of the returned tokens concatenate back to `text`. Returns
+    /// `None` for tokenizers that only approximate counts.
+    fn tokenize(&self, text: &str) -> Option<Vec<Token>> {
+        let _ = text;
+        None
+    }
+}
+
+/// A single token of a text split by [`Tokenizer::tokenize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// The token id in the tokenizer's vocabulary.
+    pub id: u32,
+    /// The part of the text the token covers. A character encoded by several
+    /// byte-level tokens belongs to the last of them, the others are empty.
+    pub text: String,
+}
+
+impl fmt::Debug for dyn Tokenizer + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Load the tokenizer selected by `--tokenizer` or the `tokenizer` config key.
+///
+/// # Arguments
+///
+/// * `name` - A tiktoken encoding or model name (see [`Encoding::from_name`]), or
+///   the path of a HuggingFace `tokenizer.json` file.
+///
+/// # Returns
+///
+/// * `Result<Box<dyn Tokenizer>>` - The loaded tokenizer, or an error if the name is unknown
+///   or the file cannot be loaded.
+pub fn load(name: &str) -> Result<Box<dyn Tokenizer>> {
+    let path = Path::new(name);
+    if name.ends_with(".json") || path.is_file() {
+        return Ok(Box::new(HuggingFaceTokenizer::from_file(path)?));
+    }
+    Ok(Box::new(Encoding::from_name(name)?))
+}
+
+/// A tiktoken BPE encoding used to count tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// The `gpt-4` / `gpt-3.5-turbo` encoding.
+    #[default]
+    Cl100k,
+    /// The `gpt-4o` / `o1` encoding.
+    O200k,
+    /// The Codex / `text-davinci-002` encoding.
+    P50k,
+    /// The GPT-3 (`davinci`) encoding.
+    R50k,
+}
+
+impl Encoding {
+    /// Resolve an encoding from its name (e.g. `o200k_base`) or from a model name
+    /// known to `tiktoken-rs` (e.g. `gpt-4o`).
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "cl100k_base" => return Ok(Encoding::Cl100k),
+            "o200k_base" => return Ok(Encoding::O200k),
+            "p50k_base" => return Ok(Encoding::P50k),
+            "r50k_base" => return Ok(Encoding::R50k),
+            _ => {}
+        }
+
+        match get_tokenizer(name) {
+            Some(TiktokenModel::Cl100kBase) => Ok(Encoding::Cl100k),
+            Some(TiktokenModel::O200kBase) => Ok(Encoding::O200k),
+            Some(TiktokenModel::P50kBase | TiktokenModel::P50kEdit) => Ok(Encoding::P50k),
+            Some(TiktokenModel::R50kBase | TiktokenModel::Gpt2) => Ok(Encoding::R50k),
+            None => Err(Error::Config(format!(
+                "Unknown tokenizer '{}': expected cl100k_base, o200k_base, p50k_base, r50k_base, a model name or a tokenizer.json path",
+                name
+            ))),
+        }
+    }
+
+    /// The canonical name of the encoding.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Cl100k => "cl100k_base",
+            Encoding::O200k => "o200k_base",
+            Encoding::P50k => "p50k_base",
+            Encoding::R50k => "r50k_base",
+        }
+    }
+
+    /// Return the shared BPE instance for this encoding, loading it on first use.
+    fn bpe(self) -> &'static CoreBPE {
+        let (cell, load): (&OnceLock<CoreBPE>, fn() -> anyhow::Result<CoreBPE>) = match self {
+            Encoding::Cl100k => (&CL100K_BASE, tiktoken_rs::cl100k_base),
+            Encoding::O200k => (&O200K_BASE, tiktoken_rs::o200k_base),
+            Encoding::P50k => (&P50K_BASE, tiktoken_rs::p50k_base),
+            Encoding::R50k => (&R50K_BASE, tiktoken_rs::r50k_base),
+        };
+        cell.get_or_init(|| load().expect("Failed to load tokenizer"))
+    }
+}
+
+impl Tokenizer for Encoding {
+    fn name(&self) -> &str {
+        self.as_str()
+    }
+
+    fn count(&self, text: &str) -> usize {
+        self.bpe().encode_with_special_tokens(text).len()
+    }
+
+    fn tokenize(&self, text: &str) -> Option<Vec<Token>> {
+        let bpe = self.bpe();
+        // A character split over several tokens only decodes once all of
+        // them are seen, so its text goes to the last one.
+        let mut pending = Vec::new();
+        let tokens = bpe
+            .encode_with_special_tokens(text)
+            .into_iter()
+            .map(|id| {
+                pending.push(id);
+                let text = match bpe.decode(pending.clone()) {
+                    Ok(piece) => {
+                        pending.clear();
+                        piece
+                    }
+                    Err(_) => String::new(),
+                };
+                Token {
+                    id: id as u32,
+                    text,
+                }
+            })
+            .collect();
+        Some(tokens)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A tokenizer loaded from a local HuggingFace `tokenizer.json` file, as
+/// shipped with Llama, Mistral or Qwen models.
+pub struct HuggingFaceTokenizer {
+    name: String,
+    cache_key: String,
+    inner: tokenizers::Tokenizer,
+}
+
+impl HuggingFaceTokenizer {
+    /// Load a tokenizer from a `tokenizer.json` file.
+    ///
+    /// Truncation and padding settings stored in the file are dropped, so
+    /// every token of the input is counted.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read tokenizer {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut inner = tokenizers::Tokenizer::from_bytes(&bytes)
+            .map_err(|e| Error::Config(format!("Invalid tokenizer {}: {}", path.display(), e)))?;
+        inner
+            .with_truncation(None)
+            .map_err(|e| Error::Config(format!("Invalid tokenizer {}: {}", path.display(), e)))?;
+        inner.with_padding(None);
+
+        // Key cached counts on the file content, so an updated tokenizer.json
+        // at the same path does not reuse stale counts. FNV-1a is used as its
+        // output, unlike that of the std hashers, never changes.
+        let mut hasher = FnvHasher::default();
+        hasher.write(&bytes);
+        let name = format!("huggingface:{}", path.display());
+        let cache_key = format!("{}#{:016x}", name, hasher.finish());
+
+        Ok(Self {
+            name,
+            cache_key,
+            inner,
+        })
+    }
+}
+
+impl Tokenizer for HuggingFaceTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn cache_key(&self) -> &str {
+        &self.cache_key
+    }
+
+    /// Count the tokens in `text`, or 0 when it cannot be encoded; file
+    /// contents are counted with [`Tokenizer::try_count_file`], which reports
+    /// the failure.
+    fn count(&self, text: &str) -> usize {
+        self.try_count_file("", text).unwrap_or(0)
+    }
+
+    fn try_count_file(&self, _path: &str, text: &str) -> Result<usize> {
+        self.inner
+            .encode_fast(text, false)
+            .map(|encoding| encoding.len())
+            .map_err(|e| Error::Tokenizer(e.to_string()))
+    }
+
+    fn tokenize(&self, text: &str) -> Option<Vec<Token>> {
+        let encoding = self.inner.encode(text, false).ok()?;
+        // Offsets are byte ranges of the input; text skipped by the
+        // normalizer or pre-tokenizer goes to the token that follows it.
+        let mut start = 0;
+        let mut tokens: Vec<Token> = encoding
+            .get_ids()
+            .iter()
+            .zip(encoding.get_offsets())
+            .map(|(&id, &(_, end))| {
+                let piece = match text.get(start..end) {
+                    Some(piece) if end > start => {
+                        start = end;
+                        piece.to_string()
+                    }
+                    _ => String::new(),
+                };
+                Token { id, text: piece }
+            })
+            .collect();
+        if let (Some(last), Some(rest)) = (tokens.last_mut(), text.get(start..)) {
+            last.text.push_str(rest);
+        }
+        Some(tokens)
+    }
+}
+
+/// A fast heuristic that approximates `cl100k_base` counts without encoding.
+///
+/// Text is split into ASCII words, digit runs, punctuation, whitespace runs
+/// and non-ASCII characters, each weighted by how many tokens it produces on
+/// average. The result is scaled by a per-language ratio. The weights and
+/// ratios were fitted against `cl100k_base` on a mixed corpus of source,
+/// config and prose files, where the mean error per file is around 7%; it is
+/// around 8% on held-out samples.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Estimator;
+
+impl Estimator {
+    /// The ratio used for languages without a calibrated one.
+    const DEFAULT_RATIO: f64 = 1.03;
+
+    fn estimate(text: &str, ratio: f64) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+
+        let mut words = 0usize;
+        let mut word_pieces = 0usize;
+        let mut digit_groups = 0usize;
+        let mut punctuation = 0usize;
+        let mut whitespace_runs = 0usize;
+        let mut non_ascii = 0usize;
+
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !c.is_ascii() {
+                non_ascii += 1;
+            } else if c.is_ascii_digit() {
+                let mut len = 1usize;
+                while chars.next_if(|c| c.is_ascii_digit()).is_some() {
+                    len += 1;
+                }
+                // Digits are encoded in groups of up to three.
+                digit_groups += len.div_ceil(3);
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let mut len = 1;
+                while chars
+                    .next_if(|c| c.is_ascii_alphabetic() || *c == '_')
+                    .is_some()
+                {
+                    len += 1;
+                }
+                // Long identifiers are split into several tokens.
+                words += 1;
+                word_pieces += (len - 1) / 4;
+            } else if c.is_ascii_whitespace() {
+                while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+                whitespace_runs += 1;
+            } else {
+                punctuation += 1;
+            }
+        }
+
+        let estimate = 0.16 * words as f64
+            + 1.12 * word_pieces as f64
+            + 2.0 * digit_groups as f64
+            + 0.57 * punctuation as f64
+            + 0.44 * whitespace_runs as f64
+            + 0.89 * non_ascii as f64;
+        ((estimate * ratio).round() as usize).max(1)
+    }
+
+    /// The calibrated token ratio for a language detected from the file path.
+    fn language_ratio(path: &str) -> f64 {
+        match detect_language(path) {
+            Some("bash") => 1.14,
+            Some("rust" | "json") => 1.04,
+            Some("python" | "c") => 1.02,
+            Some("javascript" | "yaml" | "css") => 1.0,
+            Some("toml") => 0.97,
+            Some("markdown") => 0.95,
+            Some("html") => 0.94,
+            _ => Self::DEFAULT_RATIO,
+        }
+    }
+}
+
+impl Tokenizer for Estimator {
+    fn name(&self) -> &str {
+        "estimate"
+    }
+
+    fn count(&self, text: &str) -> usize {
+        Self::estimate(text, Self::DEFAULT_RATIO)
+    }
+
+    fn count_file(&self, path: &str, text: &str) -> usize {
+        Self::estimate(text, Self::language_ratio(path))
+    }
+
+    fn is_estimate(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const WORD_LEVEL: &str = r#"{
+        "version": "1.0",
+        "truncation": {"direction": "Right", "max_length": 2, "strategy": "LongestFirst", "stride": 0},
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": {"type": "Lowercase"},
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": null,
+        "decoder": null,
+        "model": {"type": "WordLevel", "vocab": {"[UNK]": 0, "hello": 1, "world": 2}, "unk_token": "[UNK]"}
+    }"#;
+
+    #[test]
+    fn test_encoding_from_name() {
+        assert_eq!(Encoding::from_name("o200k_base").unwrap(), Encoding::O200k);
+        assert_eq!(Encoding::from_name("gpt-4o").unwrap(), Encoding::O200k);
+        assert_eq!(Encoding::from_name("gpt-4").unwrap(), Encoding::Cl100k);
+        assert_eq!(
+            Encoding::from_name("text-davinci-003").unwrap(),
+            Encoding::P50k
+        );
+        assert!(Encoding::from_name("not-a-model").is_err());
+    }
+
+    #[test]
+    fn test_encodings_differ() {
+        let text = "fn main() { println!(\"Привет, мир!\"); }";
+        let cl100k = Encoding::Cl100k.count(text);
+        let o200k = Encoding::O200k.count(text);
+        let r50k = Encoding::R50k.count(text);
+        assert!(cl100k > 0 && o200k > 0 && r50k > 0);
+        assert!(r50k > cl100k);
+    }
+
+    #[test]
+    fn test_huggingface_tokenizer() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("tokenizer.json");
+        std::fs::write(&path, WORD_LEVEL).unwrap();
+
+        let tokenizer = load(path.to_str().unwrap()).unwrap();
+        assert!(tokenizer.name().starts_with("huggingface:"));
+        assert_ne!(tokenizer.cache_key(), tokenizer.name());
+        // The truncation to 2 tokens in the file must not cap the count.
+        assert_eq!(tokenizer.count("Hello world and more"), 4);
+        let pieces: Vec<String> = tokenizer
+            .tokenize("Hello world")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.text)
+            .collect();
+        assert_eq!(pieces, ["Hello", " world"]);
+
+        assert!(load(temp_dir.path().join("missing.json").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_huggingface_cache_key_is_stable() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("tokenizer.json");
+        std::fs::write(&path, WORD_LEVEL).unwrap();
+
+        let tokenizer = load(path.to_str().unwrap()).unwrap();
+        let key = tokenizer
+            .cache_key()
+            .strip_prefix(tokenizer.name())
+            .unwrap();
+        // FNV-1a 64 of the file, which must not change between builds.
+        assert_eq!(key, "#70f842661845ca1c");
+    }
+
+    #[test]
+    fn test_huggingface_encode_error_is_reported() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("tokenizer.json");
+        // An unknown word cannot be encoded when the unknown token is missing.
+        std::fs::write(
+            &path,
+            WORD_LEVEL.replace(r#""unk_token": "[UNK]""#, r#""unk_token": "[MISSING]""#),
+        )
+        .unwrap();
+
+        let tokenizer = load(path.to_str().unwrap()).unwrap();
+        assert!(tokenizer.try_count_file("a.txt", "hello unknown").is_err());
+        let file = crate::core::file::FileData::new("a.txt", "hello unknown", tokenizer.as_ref());
+        assert_eq!(file.tokens, 0);
+        assert!(file
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("Failed to count tokens"));
+        assert_eq!(tokenizer.try_count_file("a.txt", "hello world").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_tokenize_round_trips() {
+        let text = "fn main() { println!(\"héllo 👋\"); }\n";
+        let tokens = Encoding::Cl100k.tokenize(text).unwrap();
+        assert_eq!(tokens.len(), Encoding::Cl100k.count(text));
+        assert_eq!(
+            tokens.iter().map(|t| t.text.as_str()).collect::<String>(),
+            text
+        );
+        assert_eq!(tokens[0].text, "fn");
+        assert!(Estimator.tokenize(text).is_none());
+    }
+
+    /// The per-file relative errors of the estimate against `cl100k_base`
+    /// for every file in `tests/fixtures/<name>`, with the error of the total.
+    fn estimator_errors(name: &str) -> (Vec<(PathBuf, f64)>, f64) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+
+        let (mut real_total, mut estimated_total) = (0, 0);
+        let mut errors = Vec::new();
+        for path in paths {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let real = Encoding::Cl100k.count(&text);
+            let estimated = Estimator.count_file(&path.to_string_lossy(), &text);
+            real_total += real;
+            estimated_total += estimated;
+            errors.push((path, (estimated as f64 - real as f64).abs() / real as f64));
+        }
+        let total_error = (estimated_total as f64 - real_total as f64).abs() / real_total as f64;
+        (errors, total_error)
+    }
+
+    fn assert_estimator_errors(name: &str, max_error: f64, max_mean: f64, max_total: f64) {
+        let (errors, total_error) = estimator_errors(name);
+        assert!(errors.len() >= 5);
+        for (path, error) in &errors {
+            assert!(
+                *error < max_error,
+                "{} is off by {:.1}%",
+                path.display(),
+                error * 100.0
+            );
+        }
+        let mean_error = errors.iter().map(|(_, error)| error).sum::<f64>() / errors.len() as f64;
+        assert!(
+            mean_error < max_mean,
+            "mean error {:.1}%",
+            mean_error * 100.0
+        );
+        assert!(
+            total_error < max_total,
+            "total error {:.1}%",
+            total_error * 100.0
+        );
+    }
+
+    /// The weights and ratios were fitted on `tests/fixtures/estimate`, so
+    /// this only guards against regressions, not the accuracy on other files.
+    #[test]
+    fn test_estimator_error_on_fixture_corpus() {
+        assert_estimator_errors("estimate", 0.3, 0.12, 0.05);
+    }
+
+    /// Samples that played no part in the tuning, for the accuracy to expect
+    /// on files the estimator has not seen.
+    #[test]
+    fn test_estimator_error_on_held_out_corpus() {
+        assert_estimator_errors("estimate-holdout", 0.3, 0.15, 0.1);
+    }
+
+    #[test]
+    fn test_estimator_counts() {
+        assert_eq!(Estimator.count(""), 0);
+        assert_eq!(Estimator.count("x"), 1);
+        assert!(Estimator.is_estimate());
+        assert!(!Encoding::Cl100k.is_estimate());
+        // Shell scripts have a higher ratio than Markdown for the same text.
+        let text = "echo hello world && exit 0\n";
+        assert!(Estimator.count_file("run.sh", text) > Estimator.count_file("README.md", text));
+    }
+}
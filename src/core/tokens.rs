@@ -1,35 +1,30 @@
 //! Token counting and token-based formatting functionalities.
 //!
-//! Provides the core `count_tokens` function, utilizing the `tiktoken-rs` library
-//! configured with the `gpt-4` tokenizer model, as well as functions to output token
-//! statistics in plain text or JSON format.
+//! Provides the core `count_tokens` function, which counts with any
+//! [`Tokenizer`] (`cl100k_base`, the `gpt-4` encoding, by default), as well as
+//! functions to output token statistics in plain text or JSON format,
+//! optionally with the estimated prompt cost and file priorities, and the token
+//! count of raw text.
 
 use super::file::FileData;
+use super::fit::FitReport;
+use super::pricing::{format_cost, Price};
+use super::tokenizer::{Token, Tokenizer};
+use crate::utils::priority::Priorities;
 use serde_json;
-use std::sync::OnceLock;
-use tiktoken_rs::{get_bpe_from_model, CoreBPE};
-
-// Initialize the tokenizer as a global singleton.
-static TOKENIZER: OnceLock<CoreBPE> = OnceLock::new();
-
-/// Return a reference to the global tokenizer.
-fn get_tokenizer() -> &'static CoreBPE {
-    TOKENIZER.get_or_init(|| get_bpe_from_model("gpt-4").expect("Failed to load tokenizer"))
-}
 
 /// Count the number of tokens in a given text.
 ///
-/// This uses the `gpt-4` tokenizer via the `tiktoken-rs` library.
-///
 /// # Arguments
 ///
 /// * `text` - The text content to analyze.
+/// * `tokenizer` - The tokenizer to count with.
 ///
 /// # Returns
 ///
 /// * `usize` - The calculated token count.
-pub fn count_tokens(text: &str) -> usize {
-    get_tokenizer().encode_with_special_tokens(text).len()
+pub fn count_tokens(text: &str, tokenizer: &dyn Tokenizer) -> usize {
+    tokenizer.count(text)
 }
 
 /// Format an ASCII-based display board showing files with the highest token counts.
@@ -38,34 +33,73 @@ pub fn count_tokens(text: &str) -> usize {
 ///
 /// * `files` - A slice of `FileData` representing processed files.
 /// * `max_display` - The maximum number of files to show in the board.
+/// * `tokenizer` - The tokenizer the token counts were computed with; estimated
+///   counts are marked with `~`.
+/// * `price` - When set, the prompt cost of each file and of the whole selection is shown.
+/// * `priorities` - When set, the priority of each file is shown.
 ///
 /// # Returns
 ///
 /// * `String` - A formatted string ready to be printed to standard output.
-pub fn format_token_board(files: &[FileData], max_display: usize) -> String {
+pub fn format_token_board(
+    files: &[FileData],
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+    price: Option<&Price>,
+    priorities: Option<&Priorities>,
+) -> String {
     let mut result = String::new();
     let max_path_len = files.iter().map(|f| f.path.len()).max().unwrap_or(0);
+    let approx = if tokenizer.is_estimate() { "~" } else { "" };
+    let width = max_path_len
+        + if price.is_some() { 33 } else { 20 }
+        + if priorities.is_some() { 18 } else { 0 };
 
-    result.push_str("\nToken Statistics:\n");
-    result.push_str(&"─".repeat(max_path_len + 20));
+    if tokenizer.is_estimate() {
+        result.push_str("\nToken Statistics (estimated):\n");
+    } else {
+        result.push_str("\nToken Statistics:\n");
+    }
+    result.push_str(&"─".repeat(width));
     result.push('\n');
 
     let mut sorted_files = files.to_vec();
-    sorted_files.sort_by(|a, b| b.tokens.cmp(&a.tokens));
+    sorted_files.sort_by_key(|f| std::cmp::Reverse(f.tokens));
 
     for file in sorted_files.iter().take(max_display) {
         let padding = " ".repeat(max_path_len - file.path.len());
         result.push_str(&format!(
-            "{}{} │ {:>8} tokens\n",
-            file.path, padding, file.tokens
+            "{}{} │ {:>8} tokens",
+            file.path,
+            padding,
+            format!("{}{}", approx, file.tokens)
         ));
+        if let Some(price) = price {
+            result.push_str(&format!(
+                " │ {:>10}",
+                format!("{}{}", approx, format_cost(price.cost(file.tokens)))
+            ));
+        }
+        if let Some(priorities) = priorities {
+            result.push_str(&format!(" │ priority {:>6}", priorities.weight(&file.path)));
+        }
+        result.push('\n');
     }
 
-    result.push_str(&"─".repeat(max_path_len + 20));
+    result.push_str(&"─".repeat(width));
     result.push('\n');
 
     let total_tokens: usize = files.iter().map(|f| f.tokens).sum();
-    result.push_str(&format!("Total tokens: {}\n", total_tokens));
+    result.push_str(&format!("Total tokens: {}{}\n", approx, total_tokens));
+    if let Some(price) = price {
+        result.push_str(&format!(
+            "Estimated cost ({} at ${:.2}/M input tokens): {}{}\n",
+            price.model,
+            price.per_million,
+            approx,
+            format_cost(price.cost(total_tokens))
+        ));
+    }
 
     result
 }
@@ -76,29 +110,275 @@ pub fn format_token_board(files: &[FileData], max_display: usize) -> String {
 ///
 /// * `files` - A slice of `FileData` representing processed files.
 /// * `max_display` - The maximum number of individual file entries to include in the JSON results.
+/// * `tokenizer` - The tokenizer the token counts were computed with.
+/// * `price` - When set, the prompt cost of each file and of the whole selection is included.
+/// * `priorities` - When set, the priority of each file is included.
+/// * `fit` - When set, the context window fit of the selection is included.
 ///
 /// # Returns
 ///
 /// * `String` - A JSON-formatted string representing token statistics.
-pub fn format_token_json(files: &[FileData], max_display: usize) -> String {
+pub fn format_token_json(
+    files: &[FileData],
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+    price: Option<&Price>,
+    priorities: Option<&Priorities>,
+    fit: Option<&FitReport>,
+) -> String {
+    token_json_value(files, max_display, tokenizer, price, priorities, fit).to_string()
+}
+
+/// Build the token statistics reported by [`format_token_json`] as a JSON value.
+pub fn token_json_value(
+    files: &[FileData],
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+    price: Option<&Price>,
+    priorities: Option<&Priorities>,
+    fit: Option<&FitReport>,
+) -> serde_json::Value {
     let total: usize = files.iter().map(|f| f.tokens).sum();
     let mut sorted_files = files.to_vec();
-    sorted_files.sort_by(|a, b| b.tokens.cmp(&a.tokens));
+    sorted_files.sort_by_key(|f| std::cmp::Reverse(f.tokens));
 
     let display_files: Vec<_> = sorted_files
         .iter()
         .take(max_display)
         .map(|f| {
-            serde_json::json!({
+            let mut entry = serde_json::json!({
                 "path": f.path,
                 "tokens": f.tokens,
-            })
+            });
+            if let Some(price) = price {
+                entry["cost"] = serde_json::json!(price.cost(f.tokens));
+            }
+            if let Some(priorities) = priorities {
+                entry["priority"] = serde_json::json!(priorities.weight(&f.path));
+            }
+            entry
         })
         .collect();
 
-    serde_json::json!({
+    let mut value = serde_json::json!({
+        "tokenizer": tokenizer.name(),
+        "estimated": tokenizer.is_estimate(),
         "total": total,
         "results": display_files,
+    });
+    if let Some(price) = price {
+        value["price"] = serde_json::json!({
+            "model": price.model,
+            "currency": "USD",
+            "input_per_million": price.per_million,
+        });
+        value["total_cost"] = serde_json::json!(price.cost(total));
+    }
+    if let Some(fit) = fit {
+        value["fit"] = fit.to_json();
+    }
+    value
+}
+
+/// Format the token count of raw text, such as a prompt draft or a diff.
+///
+/// # Arguments
+///
+/// * `text` - The text to count.
+/// * `per_line` - Whether to list the tokens of every line ahead of the total.
+/// * `tokenizer` - The tokenizer to count with; estimated counts are marked with `~`.
+///
+/// # Returns
+///
+/// * `String` - A formatted string ready to be printed to standard output.
+pub fn format_text_count(text: &str, per_line: bool, tokenizer: &dyn Tokenizer) -> String {
+    let approx = if tokenizer.is_estimate() { "~" } else { "" };
+    let mut result = String::new();
+
+    if per_line {
+        for (i, line) in text.lines().enumerate() {
+            result.push_str(&format!(
+                "{:>6} │ {:>8} │ {}\n",
+                i + 1,
+                format!("{}{}", approx, count_tokens(line, tokenizer)),
+                line
+            ));
+        }
+        result.push_str(&"─".repeat(40));
+        result.push('\n');
+    }
+
+    result.push_str(&format!(
+        "Total tokens: {}{} ({} lines, {} characters)\n",
+        approx,
+        count_tokens(text, tokenizer),
+        text.lines().count(),
+        text.chars().count()
+    ));
+    result
+}
+
+/// Format the token count of raw text as a JSON payload.
+///
+/// Takes the same arguments as [`format_text_count`]; the tokens of every
+/// line are listed under `lines_tokens` when `per_line` is set.
+pub fn format_text_count_json(text: &str, per_line: bool, tokenizer: &dyn Tokenizer) -> String {
+    let mut value = serde_json::json!({
+        "tokenizer": tokenizer.name(),
+        "estimated": tokenizer.is_estimate(),
+        "tokens": count_tokens(text, tokenizer),
+        "lines": text.lines().count(),
+        "characters": text.chars().count(),
+        "bytes": text.len(),
+    });
+    if per_line {
+        value["lines_tokens"] = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::json!({
+                    "line": i + 1,
+                    "tokens": count_tokens(line, tokenizer),
+                })
+            })
+            .collect();
+    }
+    value.to_string()
+}
+
+/// Background colors alternated between tokens in [`format_explain`].
+const TOKEN_COLORS: [&str; 2] = ["\x1b[48;5;24m", "\x1b[48;5;94m"];
+const RESET: &str = "\x1b[0m";
+
+/// Lines longer than this are cut in the list of most expensive lines.
+const MAX_LINE_PREVIEW: usize = 80;
+
+/// Escape the control characters of `text` other than line breaks and tabs,
+/// such as `\r` or the ESC of a terminal sequence, so that file content shown
+/// by [`format_explain`] cannot move the cursor or change the colors.
+fn escape_control(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() && c != '\n' && c != '\t' {
+            escaped.extend(c.escape_default());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// The lines of `text` with the most tokens, as `(line number, tokens, line)`,
+/// largest first.
+fn expensive_lines<'a>(
+    text: &'a str,
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<(usize, usize, &'a str)> {
+    let mut lines: Vec<(usize, usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, count_tokens(line, tokenizer), line))
+        .collect();
+    lines.sort_by_key(|&(number, tokens, _)| (std::cmp::Reverse(tokens), number));
+    lines.truncate(max_display);
+    lines
+}
+
+/// Show the token boundaries of a file and its most expensive lines.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file, shown in the heading.
+/// * `text` - The file content.
+/// * `tokens` - The tokens of `text`, from [`Tokenizer::tokenize`].
+/// * `max_display` - The maximum number of lines listed as most expensive.
+/// * `tokenizer` - The tokenizer that produced `tokens`.
+/// * `color` - Whether to alternate terminal background colors between tokens;
+///   otherwise tokens are separated by `|`.
+///
+/// # Returns
+///
+/// * `String` - A formatted string ready to be printed to standard output.
+pub fn format_explain(
+    path: &str,
+    text: &str,
+    tokens: &[Token],
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+    color: bool,
+) -> String {
+    let mut result = format!(
+        "{}: {} tokens, {} lines ({})\n",
+        path,
+        tokens.len(),
+        text.lines().count(),
+        tokenizer.name()
+    );
+    result.push_str(&"─".repeat(40));
+    result.push('\n');
+
+    for (i, token) in tokens.iter().enumerate() {
+        if !color {
+            if i > 0 {
+                result.push('|');
+            }
+            result.push_str(&escape_control(&token.text));
+            continue;
+        }
+        // Reset before every line break so colors do not bleed into the margin.
+        for segment in token.text.split_inclusive('\n') {
+            let line = segment.trim_end_matches('\n');
+            result.push_str(TOKEN_COLORS[i % 2]);
+            result.push_str(&escape_control(line));
+            result.push_str(RESET);
+            if line.len() < segment.len() {
+                result.push('\n');
+            }
+        }
+    }
+    if !text.ends_with('\n') {
+        result.push('\n');
+    }
+
+    result.push_str(&"─".repeat(40));
+    result.push_str("\nMost expensive lines:\n");
+    for (number, line_tokens, line) in expensive_lines(text, max_display, tokenizer) {
+        let preview: String = line.trim().chars().take(MAX_LINE_PREVIEW).collect();
+        result.push_str(&format!(
+            "{:>6} │ {:>6} tokens │ {}\n",
+            number,
+            line_tokens,
+            escape_control(&preview)
+        ));
+    }
+    result
+}
+
+/// Format the tokens of a file and its most expensive lines as a JSON payload.
+///
+/// Takes the same arguments as [`format_explain`], without the color switch.
+pub fn format_explain_json(
+    path: &str,
+    text: &str,
+    tokens: &[Token],
+    max_display: usize,
+    tokenizer: &dyn Tokenizer,
+) -> String {
+    serde_json::json!({
+        "path": path,
+        "tokenizer": tokenizer.name(),
+        "tokens": tokens.len(),
+        "pieces": tokens
+            .iter()
+            .map(|token| serde_json::json!({ "id": token.id, "text": token.text }))
+            .collect::<Vec<_>>(),
+        "lines": expensive_lines(text, max_display, tokenizer)
+            .into_iter()
+            .map(|(number, tokens, line)| {
+                serde_json::json!({ "line": number, "tokens": tokens, "text": line })
+            })
+            .collect::<Vec<_>>(),
     })
     .to_string()
 }
@@ -106,37 +386,149 @@ pub fn format_token_json(files: &[FileData], max_display: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::tokenizer::{Encoding, Estimator};
+    use std::path::Path;
 
     #[test]
     fn test_token_counting() {
         let text = "Hello, world!";
-        assert!(count_tokens(text) > 0);
+        assert!(count_tokens(text, &Encoding::default()) > 0);
     }
 
     #[test]
     fn test_token_board_formatting() {
         let files = vec![
-            FileData::new("test1.txt", "content1"),
-            FileData::new("test2.txt", "content2"),
+            FileData::new("test1.txt", "content1", &Encoding::default()),
+            FileData::new("test2.txt", "content2", &Encoding::default()),
         ];
 
-        let board = format_token_board(&files, 2);
+        let board = format_token_board(&files, 2, &Encoding::default(), None, None);
         assert!(board.contains("test1.txt"));
         assert!(board.contains("test2.txt"));
         assert!(board.contains("tokens"));
     }
 
+    #[test]
+    fn test_token_board_marks_estimates() {
+        let files = vec![FileData::new("test1.txt", "content1", &Estimator)];
+
+        let board = format_token_board(&files, 1, &Estimator, None, None);
+        assert!(board.contains("Token Statistics (estimated):"));
+        assert!(board.contains(&format!("Total tokens: ~{}", files[0].tokens)));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_token_json(&files, 1, &Estimator, None, None, None))
+                .unwrap();
+        assert_eq!(json["tokenizer"], "estimate");
+        assert_eq!(json["estimated"], true);
+    }
+
     #[test]
     fn test_token_json_formatting() {
         let files = vec![
-            FileData::new("test1.txt", "content1"),
-            FileData::new("test2.txt", "content2"),
+            FileData::new("test1.txt", "content1", &Encoding::default()),
+            FileData::new("test2.txt", "content2", &Encoding::default()),
         ];
 
-        let json = format_token_json(&files, 2);
+        let json = format_token_json(&files, 2, &Encoding::O200k, None, None, None);
         let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
 
+        assert_eq!(parsed["tokenizer"], "o200k_base");
         assert!(parsed["total"].as_u64().is_some());
         assert!(parsed["results"].as_array().unwrap().len() == 2);
     }
+
+    #[test]
+    fn test_token_board_priorities() {
+        let files = vec![
+            FileData::new("README.md", "# Title", &Encoding::default()),
+            FileData::new("tests/it.rs", "fn it() {}", &Encoding::default()),
+        ];
+        let priorities = Priorities::new(
+            &[
+                ("README.md".to_string(), 10.0),
+                ("tests/**".to_string(), 0.25),
+            ],
+            Path::new("."),
+        )
+        .unwrap();
+
+        let board = format_token_board(&files, 2, &Encoding::default(), None, Some(&priorities));
+        assert!(board.contains("│ priority     10\n"));
+        assert!(board.contains("│ priority   0.25\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&format_token_json(
+            &files,
+            2,
+            &Encoding::default(),
+            None,
+            Some(&priorities),
+            None,
+        ))
+        .unwrap();
+        let results = json["results"].as_array().unwrap();
+        assert!(results
+            .iter()
+            .any(|r| r["path"] == "tests/it.rs" && r["priority"] == 0.25));
+    }
+
+    #[test]
+    fn test_text_count() {
+        let encoding = Encoding::default();
+        let text = "Hello, world!\nSecond line\n";
+
+        let plain = format_text_count(text, false, &encoding);
+        assert_eq!(
+            plain,
+            format!(
+                "Total tokens: {} (2 lines, 26 characters)\n",
+                count_tokens(text, &encoding)
+            )
+        );
+        let per_line = format_text_count(text, true, &encoding);
+        assert!(per_line.contains("     1 │        4 │ Hello, world!\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_text_count_json(text, true, &encoding)).unwrap();
+        assert_eq!(json["lines"], 2);
+        assert_eq!(json["bytes"], 26);
+        assert_eq!(json["lines_tokens"][1]["line"], 2);
+        assert_eq!(json["lines_tokens"][0]["tokens"], 4);
+    }
+
+    #[test]
+    fn test_explain() {
+        let encoding = Encoding::default();
+        let text = "a\nlet longer_line = compute(1, 2, 3);\n";
+        let tokens = encoding.tokenize(text).unwrap();
+
+        let plain = format_explain("x.rs", text, &tokens, 1, &encoding, false);
+        assert!(plain.starts_with(&format!("x.rs: {} tokens, 2 lines", tokens.len())));
+        assert!(plain.contains("let| longer"));
+        assert!(plain.contains("     2 │"));
+        assert!(!plain.contains("     1 │"));
+
+        let colored = format_explain("x.rs", text, &tokens, 1, &encoding, true);
+        assert!(colored.contains(TOKEN_COLORS[1]));
+
+        // Control characters from the file are shown escaped, not sent to
+        // the terminal; only the token colors are.
+        let hostile = "x = 1\r\n\x1b[2Jy\tz\n";
+        let hostile_tokens = encoding.tokenize(hostile).unwrap();
+        let colored = format_explain("x.rs", hostile, &hostile_tokens, 2, &encoding, true);
+        let raw = colored
+            .replace(TOKEN_COLORS[0], "")
+            .replace(TOKEN_COLORS[1], "")
+            .replace(RESET, "");
+        assert!(!raw.contains('\x1b') && !raw.contains('\r'));
+        assert!(raw.contains("\\u{1b}[2Jy\tz"));
+        assert!(raw.contains("x = 1\\r\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_explain_json("x.rs", text, &tokens, 5, &encoding))
+                .unwrap();
+        assert_eq!(json["pieces"].as_array().unwrap().len(), tokens.len());
+        assert_eq!(json["lines"][0]["line"], 2);
+        assert_eq!(json["lines"][1]["text"], "a");
+    }
 }
@@ -4,6 +4,7 @@
 //! directory tree from a flat list of files and visually format it as an ASCII tree.
 
 use super::file::FileData;
+use crate::utils::priority::Priorities;
 use serde::Serialize;
 use std::collections::HashMap;
 
@@ -16,6 +17,9 @@ pub struct TreeNode {
     pub tokens: usize,
     /// Sub-nodes for a directory, or empty if it's a file.
     pub children: Vec<TreeNode>,
+    /// The priority of a file, when priority rules are shown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<f64>,
 }
 
 impl TreeNode {
@@ -25,6 +29,7 @@ impl TreeNode {
             path,
             tokens: 0,
             children: Vec::new(),
+            priority: None,
         }
     }
 
@@ -110,6 +115,33 @@ fn update_directory_tokens(node: &mut TreeNode) -> usize {
     total
 }
 
+/// Set the priority of every file in the tree from the priority rules.
+///
+/// # Arguments
+///
+/// * `tree` - The root `TreeNode` returned by [`build_tree`].
+/// * `priorities` - The priority rules of the context.
+pub fn assign_priorities(tree: &mut TreeNode, priorities: &Priorities) {
+    fn assign(node: &mut TreeNode, path: &str, priorities: &Priorities) {
+        if node.children.is_empty() {
+            node.priority = Some(priorities.weight(path));
+        }
+        for child in &mut node.children {
+            let child_path = if path.is_empty() {
+                child.path.clone()
+            } else {
+                format!("{}/{}", path, child.path)
+            };
+            assign(child, &child_path, priorities);
+        }
+    }
+
+    for child in &mut tree.children {
+        let path = child.path.clone();
+        assign(child, &path, priorities);
+    }
+}
+
 /// Recursively formats the tree structure into a visually aligned ASCII representation.
 ///
 /// # Arguments
@@ -126,9 +158,13 @@ pub fn format_tree(tree: &TreeNode, indent: &str, is_last: bool) -> String {
 
     if !tree.path.is_empty() {
         let marker = if is_last { "└── " } else { "├── " };
+        let priority = match tree.priority {
+            Some(priority) => format!(", priority {}", priority),
+            None => String::new(),
+        };
         result.push_str(&format!(
-            "{}{}{} ({} tokens)\n",
-            indent, marker, tree.path, tree.tokens
+            "{}{}{} ({} tokens{})\n",
+            indent, marker, tree.path, tree.tokens, priority
         ));
     }
 
@@ -151,13 +187,15 @@ pub fn format_tree(tree: &TreeNode, indent: &str, is_last: bool) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::tokenizer::Encoding;
+    use std::path::Path;
 
     #[test]
     fn test_tree_building() {
         let files = vec![
-            FileData::new("src/main.rs", "content1"),
-            FileData::new("src/lib.rs", "content2"),
-            FileData::new("README.md", "content3"),
+            FileData::new("src/main.rs", "content1", &Encoding::default()),
+            FileData::new("src/lib.rs", "content2", &Encoding::default()),
+            FileData::new("README.md", "content3", &Encoding::default()),
         ];
 
         let tree = build_tree(&files);
@@ -187,8 +225,8 @@ mod tests {
     #[test]
     fn test_tree_formatting() {
         let files = vec![
-            FileData::new("src/main.rs", "content1"),
-            FileData::new("src/lib.rs", "content2"),
+            FileData::new("src/main.rs", "content1", &Encoding::default()),
+            FileData::new("src/lib.rs", "content2", &Encoding::default()),
         ];
 
         let tree = build_tree(&files);
@@ -199,4 +237,29 @@ mod tests {
         assert!(formatted.contains("lib.rs"));
         assert!(formatted.contains("tokens"));
     }
+
+    #[test]
+    fn test_tree_priorities() {
+        let files = vec![
+            FileData::new("src/main.rs", "content1", &Encoding::default()),
+            FileData::new("tests/it.rs", "content2", &Encoding::default()),
+        ];
+        let priorities = Priorities::new(
+            &[
+                ("src/main.rs".to_string(), 5.0),
+                ("tests/**".to_string(), 0.5),
+            ],
+            Path::new("."),
+        )
+        .unwrap();
+
+        let mut tree = build_tree(&files);
+        assign_priorities(&mut tree, &priorities);
+        let formatted = format_tree(&tree, "", true);
+
+        let tokens = files[0].tokens;
+        assert!(formatted.contains(&format!("main.rs ({} tokens, priority 5)", tokens)));
+        assert!(formatted.contains(&format!("it.rs ({} tokens, priority 0.5)", tokens)));
+        assert!(formatted.contains(&format!("src ({} tokens)\n", tokens)));
+    }
 }
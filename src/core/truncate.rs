@@ -0,0 +1,204 @@
+//! Truncating oversized files to a token budget.
+//!
+//! Cuts a file down on line boundaries so it fits the per-file budget, and
+//! puts an explicit `[... N lines / M tokens omitted ...]` marker where lines
+//! were left out, so files over `--max-budget` can be kept instead of dropped.
+
+use super::file::FileData;
+use super::tokenizer::Tokenizer;
+use crate::error::{Error, Result};
+
+/// Which part of an oversized file is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    /// Keep the leading lines, with the marker at the end.
+    Head,
+    /// Keep the leading and trailing lines, dropping the middle of the file:
+    /// the budget is split evenly between both ends around the marker.
+    HeadTail,
+}
+
+impl Truncation {
+    /// Parse a `--truncate` value: `head`, or `head-tail` and its alias
+    /// `middle`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "head" => Ok(Self::Head),
+            "head-tail" | "middle" => Ok(Self::HeadTail),
+            _ => Err(Error::Config(format!(
+                "Unknown truncation strategy: {}",
+                name
+            ))),
+        }
+    }
+
+    /// The name of the strategy, as accepted by [`Truncation::from_name`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Head => "head",
+            Self::HeadTail => "head-tail",
+        }
+    }
+
+    /// The share of `available` tokens given to the leading lines.
+    fn head_share(&self, available: usize) -> usize {
+        match self {
+            Self::Head => available,
+            Self::HeadTail => available / 2,
+        }
+    }
+}
+
+fn omitted_marker(lines: usize, tokens: usize) -> String {
+    format!("[... {} lines / {} tokens omitted ...]\n", lines, tokens)
+}
+
+/// Cut a file down to at most `max_tokens` tokens, marker included.
+///
+/// Files within the budget are returned unchanged, and so are files where not
+/// a single whole line fits next to the marker, as cuts only land on line
+/// boundaries. The token count of the returned file is that of its truncated
+/// content.
+///
+/// # Arguments
+///
+/// * `file` - The file to truncate.
+/// * `max_tokens` - The per-file token budget.
+/// * `truncation` - Which part of the file is kept.
+/// * `tokenizer` - The tokenizer used to count the tokens.
+///
+/// # Returns
+///
+/// * `FileData` - The file, truncated when it was over the budget.
+pub fn truncate_file(
+    file: FileData,
+    max_tokens: usize,
+    truncation: Truncation,
+    tokenizer: &dyn Tokenizer,
+) -> FileData {
+    if file.tokens <= max_tokens || file.content.is_empty() {
+        return file;
+    }
+
+    let lines: Vec<&str> = file.content.split_inclusive('\n').collect();
+    let line_tokens: Vec<usize> = lines.iter().map(|line| tokenizer.count(line)).collect();
+    let marker = tokenizer.count(&omitted_marker(lines.len(), file.tokens));
+    let available = max_tokens.saturating_sub(marker);
+
+    let mut head = 0;
+    let mut used = 0;
+    let head_share = truncation.head_share(available);
+    while head < lines.len() && used + line_tokens[head] <= head_share {
+        used += line_tokens[head];
+        head += 1;
+    }
+    let mut tail = 0;
+    if truncation != Truncation::Head {
+        while head + tail < lines.len() && used + line_tokens[lines.len() - 1 - tail] <= available {
+            used += line_tokens[lines.len() - 1 - tail];
+            tail += 1;
+        }
+    }
+    if head + tail == 0 {
+        return file;
+    }
+
+    // Per-line counts only approximate the count of the joined lines, so
+    // give back lines until the whole file fits.
+    loop {
+        let omitted = &lines[head..lines.len() - tail];
+        let mut content: String = lines[..head].concat();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&omitted_marker(
+            omitted.len(),
+            tokenizer.count(&omitted.concat()),
+        ));
+        content.push_str(&lines[lines.len() - tail..].concat());
+
+        let truncated = FileData::new(file.path.clone(), content, tokenizer);
+        if truncated.tokens <= max_tokens {
+            return truncated;
+        }
+        if tail > 0 {
+            tail -= 1;
+        } else {
+            head -= 1;
+        }
+        if head + tail == 0 {
+            return file;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    fn numbered_file(count: usize) -> FileData {
+        let content: String = (1..=count)
+            .map(|i| format!("line number {}\n", i))
+            .collect();
+        FileData::new("src/big.rs", content, &Encoding::default())
+    }
+
+    #[test]
+    fn test_small_files_are_unchanged() {
+        let file = numbered_file(3);
+        let tokens = file.tokens;
+        let kept = truncate_file(file, tokens, Truncation::Head, &Encoding::default());
+        assert_eq!(kept.tokens, tokens);
+        assert!(!kept.content.contains("omitted"));
+    }
+
+    #[test]
+    fn test_truncation_strategies() {
+        let encoding = Encoding::default();
+        let file = numbered_file(200);
+
+        let head = truncate_file(file.clone(), 100, Truncation::Head, &encoding);
+        assert!(head.tokens <= 100);
+        assert_eq!(head.tokens, encoding.count(&head.content));
+        assert!(head.content.starts_with("line number 1\n"));
+        assert!(head.content.ends_with("tokens omitted ...]\n"));
+
+        let head_tail = truncate_file(file, 100, Truncation::HeadTail, &encoding);
+        assert!(head_tail.tokens <= 100);
+        assert!(head_tail.content.starts_with("line number 1\n"));
+        assert!(head_tail.content.ends_with("line number 200\n"));
+
+        let lines: Vec<&str> = head_tail.content.lines().collect();
+        let marker = lines.iter().position(|l| l.starts_with("[...")).unwrap();
+        let omitted = 200 - (lines.len() - 1);
+        assert!(lines[marker].starts_with(&format!("[... {} lines / ", omitted)));
+        // The budget is split evenly around the marker.
+        assert!(marker.abs_diff(lines.len() - 1 - marker) <= 2);
+
+        assert_eq!(
+            Truncation::from_name("middle").unwrap(),
+            Truncation::HeadTail
+        );
+    }
+
+    #[test]
+    fn test_oversized_first_line_is_not_cut() {
+        let encoding = Encoding::default();
+        let long_line = "word ".repeat(200) + "\n";
+        let file = FileData::new("min.js", long_line.clone() + "short\n", &encoding);
+        let tokens = file.tokens;
+
+        // Not a single line fits before the marker: the file is left whole,
+        // for the budget filter to drop, instead of becoming a bare marker.
+        let kept = truncate_file(file.clone(), 50, Truncation::Head, &encoding);
+        assert_eq!(kept.tokens, tokens);
+        assert_eq!(kept.content, file.content);
+
+        // The trailing line still fits with head-tail.
+        let kept = truncate_file(file, 50, Truncation::HeadTail, &encoding);
+        assert!(kept.tokens <= 50);
+        assert!(kept.content.starts_with("[... 1 lines / "));
+        assert!(kept.content.ends_with("short\n"));
+    }
+}
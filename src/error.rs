@@ -22,6 +22,21 @@ pub enum Error {
     
     #[error("Cache error: {0}")]
     Cache(String),
+
+    #[error("Template error: {0}")]
+    Template(String),
+
+    #[error("Unmerge error: {0}")]
+    Unmerge(String),
+
+    #[error("Context window exceeded: {0}")]
+    Overflow(String),
+
+    #[error("Read error: {0}")]
+    Read(String),
+
+    #[error("Tokenizer error: {0}")]
+    Tokenizer(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
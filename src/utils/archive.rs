@@ -0,0 +1,368 @@
+//! Archive output for the merge command.
+//!
+//! Writes the selected files as a tar or zip archive instead of a single
+//! concatenated document. Paths are stored relative to the merge root, file
+//! content, permissions and modification times are taken from disk, and a
+//! `codemerge-manifest.json` entry records the token count of every file.
+
+use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the manifest entry added to every archive.
+pub const MANIFEST_NAME: &str = "codemerge-manifest.json";
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    tokenizer: &'a str,
+    estimated: bool,
+    total_tokens: usize,
+    files: Vec<ManifestEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    path: String,
+    source: &'a str,
+    tokens: usize,
+    bytes: usize,
+}
+
+/// Metadata of a file as it will be stored in the archive.
+struct Entry<'a> {
+    path: String,
+    file: &'a FileData,
+    content: Vec<u8>,
+    mode: u32,
+    mtime: u64,
+}
+
+/// Write the files into a tar or zip archive at `output`.
+///
+/// Entries hold the bytes of each file as read from disk, so they are not
+/// affected by the line numbers or other changes made to the merged content;
+/// the manifest keeps the token counts of `files`.
+///
+/// # Arguments
+///
+/// * `files` - The files to archive.
+/// * `format` - Either "tar" or "zip".
+/// * `root` - The merge root that archive paths are made relative to.
+/// * `output` - The path of the archive to create.
+/// * `tokenizer` - The tokenizer the token counts were computed with.
+pub fn write_archive(
+    files: &[FileData],
+    format: &str,
+    root: &Path,
+    output: &Path,
+    tokenizer: &dyn Tokenizer,
+) -> Result<()> {
+    let mut stored = HashSet::new();
+    let mut paths = Vec::with_capacity(files.len());
+    for file in files {
+        let path = archive_path(Path::new(&file.path), root)?;
+        if path == MANIFEST_NAME || !stored.insert(path.clone()) {
+            return Err(anyhow::anyhow!(
+                "{} would be stored in the archive as {}, which is already taken",
+                file.path,
+                path
+            ));
+        }
+        paths.push(path);
+    }
+    let entries = files
+        .iter()
+        .zip(paths)
+        .map(|(file, path)| {
+            let content = std::fs::read(&file.path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.path, e))?;
+            let (mode, mtime) = file_metadata(Path::new(&file.path));
+            Ok(Entry {
+                path,
+                file,
+                content,
+                mode,
+                mtime,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let manifest = Manifest {
+        tokenizer: tokenizer.name(),
+        estimated: tokenizer.is_estimate(),
+        total_tokens: files.iter().map(|f| f.tokens).sum(),
+        files: entries
+            .iter()
+            .map(|e| ManifestEntry {
+                path: e.path.clone(),
+                source: &e.file.path,
+                tokens: e.file.tokens,
+                bytes: e.content.len(),
+            })
+            .collect(),
+    };
+    let manifest = serde_json::to_string_pretty(&manifest)?;
+    let now = unix_seconds(SystemTime::now());
+
+    let out = File::create(output)?;
+    match format {
+        "tar" => write_tar(out, &entries, &manifest, now),
+        "zip" => write_zip(out, &entries, &manifest, now),
+        _ => Err(anyhow::anyhow!("Unsupported archive format: {}", format)),
+    }
+}
+
+fn write_tar(out: File, entries: &[Entry], manifest: &str, now: u64) -> Result<()> {
+    let mut builder = tar::Builder::new(out);
+
+    let mut append = |path: &str, content: &[u8], mode: u32, mtime: u64| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, path, content)?;
+        Ok(())
+    };
+
+    for entry in entries {
+        append(&entry.path, &entry.content, entry.mode, entry.mtime)?;
+    }
+    append(MANIFEST_NAME, manifest.as_bytes(), 0o644, now)?;
+
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+fn write_zip(out: File, entries: &[Entry], manifest: &str, now: u64) -> Result<()> {
+    use zip::write::SimpleFileOptions;
+
+    let mut writer = zip::ZipWriter::new(out);
+    let options = |mode: u32, mtime: u64| {
+        SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(mode)
+            .last_modified_time(zip_time(mtime))
+    };
+
+    for entry in entries {
+        writer.start_file(entry.path.as_str(), options(entry.mode, entry.mtime))?;
+        writer.write_all(&entry.content)?;
+    }
+    writer.start_file(MANIFEST_NAME, options(0o644, now))?;
+    writer.write_all(manifest.as_bytes())?;
+
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+/// Build the in-archive path of a file: relative to `root`, using `/`
+/// separators and without any `.` or root components.
+///
+/// Paths with `..` components are refused, as dropping them could make two
+/// files share a name.
+fn archive_path(path: &Path, root: &Path) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in path.strip_prefix(root).unwrap_or(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::ParentDir => {
+                return Err(anyhow::anyhow!(
+                    "Cannot store {} in the archive: paths may not contain '..'",
+                    path.display()
+                ))
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// Read the permission bits and modification time of a file, falling back
+/// to `0o644` and the current time when they are unavailable.
+fn file_metadata(path: &Path) -> (u32, u64) {
+    let metadata = std::fs::metadata(path).ok();
+    let mtime = metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .map(unix_seconds)
+        .unwrap_or_else(|| unix_seconds(SystemTime::now()));
+
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata
+            .map(|m| m.permissions().mode() & 0o7777)
+            .unwrap_or(0o644)
+    };
+    #[cfg(not(unix))]
+    let mode = 0o644;
+
+    (mode, mtime)
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Convert UNIX seconds (UTC) into a zip timestamp.
+///
+/// Zip timestamps cannot represent dates before 1980, which are clamped.
+fn zip_time(seconds: u64) -> zip::DateTime {
+    let days = (seconds / 86_400) as i64;
+    let secs_of_day = seconds % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + i64::from(month <= 2)) as u16;
+
+    zip::DateTime::from_date_and_time(
+        year,
+        month,
+        day,
+        (secs_of_day / 3600) as u8,
+        (secs_of_day % 3600 / 60) as u8,
+        (secs_of_day % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+    use std::io::Read;
+
+    #[test]
+    fn test_archive_path() {
+        assert_eq!(
+            archive_path(Path::new("./src/main.rs"), Path::new(".")).unwrap(),
+            "src/main.rs"
+        );
+        assert_eq!(
+            archive_path(Path::new("/repo/src/lib.rs"), Path::new("/repo")).unwrap(),
+            "src/lib.rs"
+        );
+        assert_eq!(
+            archive_path(Path::new("/elsewhere/x.rs"), Path::new("/repo")).unwrap(),
+            "elsewhere/x.rs"
+        );
+        assert!(archive_path(Path::new("a/../x.rs"), Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_colliding_paths_are_refused() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let files = vec![
+            FileData::new("/elsewhere/x.rs", "a", &Encoding::default()),
+            FileData::new("elsewhere/x.rs", "b", &Encoding::default()),
+        ];
+        let output = temp_dir.path().join("out.tar");
+        let result = write_archive(&files, "tar", Path::new("."), &output, &Encoding::default());
+        assert!(result.unwrap_err().to_string().contains("already taken"));
+    }
+
+    #[test]
+    fn test_zip_time() {
+        // 2024-02-29 13:45:30 UTC
+        let time = zip_time(1_709_214_330);
+        assert_eq!((time.year(), time.month(), time.day()), (2024, 2, 29));
+        assert_eq!((time.hour(), time.minute(), time.second()), (13, 45, 30));
+    }
+
+    #[test]
+    fn test_write_tar_and_zip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("repo");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        let source = root.join("src/main.rs");
+        std::fs::write(&source, "fn main() {}\n").unwrap();
+        let mtime = UNIX_EPOCH + std::time::Duration::from_secs(1_709_214_330);
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        // Merged content with line numbers, which the archive must not store.
+        let files = vec![FileData::new(
+            source.to_string_lossy().to_string(),
+            "1 | fn main() {}\n",
+            &Encoding::default(),
+        )];
+
+        let tar_path = temp_dir.path().join("out.tar");
+        write_archive(&files, "tar", &root, &tar_path, &Encoding::default()).unwrap();
+        let mut archive = tar::Archive::new(File::open(&tar_path).unwrap());
+        let entries: Vec<(String, u32, u64)> = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                let header = e.header();
+                (
+                    e.path().unwrap().to_string_lossy().to_string(),
+                    header.mode().unwrap(),
+                    header.mtime().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(entries[0].0, "src/main.rs");
+        assert_eq!(entries[0].2, 1_709_214_330);
+        #[cfg(unix)]
+        assert_eq!(entries[0].1, 0o755);
+        assert_eq!(entries[1].0, MANIFEST_NAME);
+
+        let zip_path = temp_dir.path().join("out.zip");
+        write_archive(&files, "zip", &root, &zip_path, &Encoding::default()).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+        let mut content = String::new();
+        let mut entry = archive.by_name("src/main.rs").unwrap();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "fn main() {}\n");
+        let modified = entry.last_modified().unwrap();
+        assert_eq!(
+            (modified.year(), modified.month(), modified.day()),
+            (2024, 2, 29)
+        );
+        assert_eq!(
+            (modified.hour(), modified.minute(), modified.second()),
+            (13, 45, 30)
+        );
+        #[cfg(unix)]
+        assert_eq!(entry.unix_mode().unwrap() & 0o7777, 0o755);
+        drop(entry);
+
+        let mut manifest = String::new();
+        archive
+            .by_name(MANIFEST_NAME)
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(manifest["files"][0]["path"], "src/main.rs");
+        assert_eq!(manifest["files"][0]["bytes"], 13);
+        assert_eq!(manifest["files"][0]["tokens"], files[0].tokens);
+        assert_eq!(manifest["tokenizer"], "cl100k_base");
+        assert_eq!(manifest["total_tokens"], files[0].tokens);
+    }
+}
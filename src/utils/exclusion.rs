@@ -0,0 +1,295 @@
+//! Reporting why candidate files were left out.
+//!
+//! File discovery and the budget filters drop files silently. This module
+//! repeats the discovery of [`finder::find_files`] while recording, for every
+//! path it leaves out, the rule responsible: the ignore file and line, the
+//! include or ignore glob, and later the empty content, read error or budget
+//! bound that excluded a file.
+
+use super::finder::{self, build_glob_set};
+use crate::error::Result;
+use ignore::gitignore::Gitignore;
+use ignore::{Match, WalkBuilder};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Ignore files checked in every directory, in order of precedence.
+const IGNORE_FILES: &[&str] = &[".ignore", ".gitignore"];
+
+/// A candidate file or directory left out of the selection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exclusion {
+    /// The path, as the files found are named, with a trailing `/` for
+    /// directories.
+    pub path: String,
+    /// The rule that excluded it.
+    pub reason: String,
+}
+
+/// Where a list of glob patterns comes from, such as `--filters` or the
+/// `ignores` of a config context.
+#[derive(Debug, Clone, Copy)]
+pub struct PatternSource<'a> {
+    /// The patterns.
+    pub patterns: &'a [String],
+    /// How the patterns are named in the report.
+    pub origin: &'a str,
+}
+
+/// Find files like [`finder::find_files`], and report every file or directory
+/// under `root` that is left out, with the rule that excluded it.
+///
+/// Directories skipped by an ignore file are reported once, without listing
+/// their content.
+///
+/// # Arguments
+///
+/// * `root` - The directory to search.
+/// * `include` - The glob patterns a file must match.
+/// * `ignore` - The glob patterns that exclude a file.
+///
+/// # Returns
+///
+/// * `Result<(Vec<String>, Vec<Exclusion>)>` - The files found, as
+///   [`finder::find_files`] returns them, and the excluded paths.
+pub fn find_files_explained(
+    root: &Path,
+    include: PatternSource,
+    ignore: PatternSource,
+) -> Result<(Vec<String>, Vec<Exclusion>)> {
+    let include_set = build_glob_set(include.patterns)?;
+    let ignore_set = build_glob_set(ignore.patterns)?;
+
+    // Every entry the regular walk visits; any other entry was skipped by an
+    // ignore file.
+    let visited: Arc<HashSet<PathBuf>> = Arc::new(
+        finder::walk_builder(root)
+            .build()
+            .flatten()
+            .map(|entry| entry.into_path())
+            .collect(),
+    );
+
+    let skipped: Arc<Mutex<Vec<(PathBuf, bool)>>> = Arc::default();
+    let mut builder = WalkBuilder::new(root);
+    builder.standard_filters(false).hidden(false);
+    {
+        let visited = Arc::clone(&visited);
+        let skipped = Arc::clone(&skipped);
+        builder.filter_entry(move |entry| {
+            let keep = visited.contains(entry.path());
+            if !keep {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                if let Ok(mut skipped) = skipped.lock() {
+                    skipped.push((entry.path().to_path_buf(), is_dir));
+                }
+            }
+            keep
+        });
+    }
+
+    let mut files = Vec::new();
+    let mut excluded = Vec::new();
+    for entry in builder.build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if !include_set.is_match(relative) {
+            excluded.push(Exclusion {
+                path: path.to_string_lossy().to_string(),
+                reason: format!("not matched by any {} pattern", include.origin),
+            });
+        } else if let Some(index) = ignore_set.matches(relative).into_iter().min() {
+            excluded.push(Exclusion {
+                path: path.to_string_lossy().to_string(),
+                reason: format!(
+                    "matches {} pattern '{}'",
+                    ignore.origin, ignore.patterns[index]
+                ),
+            });
+        } else {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    let skipped = std::mem::take(&mut *skipped.lock().unwrap_or_else(|e| e.into_inner()));
+    let mut rules = IgnoreRules::new(root);
+    for (path, is_dir) in skipped {
+        let mut display = path.to_string_lossy().to_string();
+        if is_dir {
+            display.push('/');
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let reason = match rules.find(relative, is_dir) {
+            Some(rule) => format!("ignored by {}", rule),
+            None => "ignored by a global gitignore or git exclude rule".to_string(),
+        };
+        excluded.push(Exclusion {
+            path: display,
+            reason,
+        });
+    }
+
+    Ok((files, excluded))
+}
+
+/// Looks up the ignore file rule that excludes a path, caching the parsed
+/// ignore files.
+struct IgnoreRules {
+    root: PathBuf,
+    base: PathBuf,
+    matchers: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl IgnoreRules {
+    fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            base: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            matchers: HashMap::new(),
+        }
+    }
+
+    /// Describe the rule excluding `relative` as `file:line (pattern)`, from
+    /// the ignore files of its directory and its parents, up to the
+    /// repository root. Deeper ignore files take precedence.
+    fn find(&mut self, relative: &Path, is_dir: bool) -> Option<String> {
+        let path = self.base.join(relative);
+        for dir in path.ancestors().skip(1) {
+            let mut candidates: Vec<PathBuf> =
+                IGNORE_FILES.iter().map(|name| dir.join(name)).collect();
+            let is_repository = dir.join(".git").exists();
+            if is_repository {
+                candidates.push(dir.join(".git/info/exclude"));
+            }
+
+            for file in candidates {
+                let matcher = self
+                    .matchers
+                    .entry(file.clone())
+                    .or_insert_with(|| file.is_file().then(|| Gitignore::new(&file).0));
+                match matcher
+                    .as_ref()
+                    .map(|m| m.matched_path_or_any_parents(&path, is_dir))
+                {
+                    Some(Match::Ignore(glob)) => {
+                        return Some(describe_rule(
+                            &self.root,
+                            &self.base,
+                            &file,
+                            glob.original(),
+                        ))
+                    }
+                    Some(Match::Whitelist(_)) => return None,
+                    _ => {}
+                }
+            }
+            if is_repository {
+                break;
+            }
+        }
+        None
+    }
+}
+
+/// Describe a rule of an ignore `file` as `file:line (pattern)`, showing files
+/// under `base`, the canonical `root`, relative to `root`.
+fn describe_rule(root: &Path, base: &Path, file: &Path, pattern: &str) -> String {
+    let line = std::fs::read_to_string(file)
+        .ok()
+        .and_then(|content| content.lines().position(|l| l.trim() == pattern))
+        .map(|index| format!(":{}", index + 1))
+        .unwrap_or_default();
+    let shown = match file.strip_prefix(base) {
+        Ok(relative) => root.join(relative),
+        Err(_) => file.to_path_buf(),
+    };
+    format!("{}{} ({})", shown.display(), line, pattern)
+}
+
+/// Format the exclusion report, one excluded path per line in path order, for
+/// printing to stderr.
+pub fn format_report(excluded: &[Exclusion], included: usize) -> String {
+    let mut excluded: Vec<&Exclusion> = excluded.iter().collect();
+    excluded.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut result = format!(
+        "Exclusion report: {} files included, {} paths excluded\n",
+        included,
+        excluded.len()
+    );
+    let max_path_len = excluded.iter().map(|e| e.path.len()).max().unwrap_or(0);
+    for exclusion in &excluded {
+        result.push_str(&format!(
+            "  {:<width$} │ {}\n",
+            exclusion.path,
+            exclusion.reason,
+            width = max_path_len
+        ));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_files_explained() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::create_dir_all(root.join("build"))?;
+        fs::write(root.join(".gitignore"), "# build output\nbuild/\n*.log\n")?;
+        fs::write(root.join("src/main.rs"), "fn main() {}")?;
+        fs::write(root.join("src/notes.txt"), "notes")?;
+        fs::write(root.join("src/debug.log"), "log")?;
+        fs::write(root.join("src/generated.rs"), "")?;
+        fs::write(root.join("build/out.rs"), "")?;
+
+        let include = vec!["**/*.rs".to_string()];
+        let ignore = vec!["**/generated.rs".to_string()];
+        let (files, excluded) = find_files_explained(
+            root,
+            PatternSource {
+                patterns: &include,
+                origin: "--filters",
+            },
+            PatternSource {
+                patterns: &ignore,
+                origin: "config ignores",
+            },
+        )?;
+
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("main.rs"));
+
+        let reason = |path: &str| {
+            excluded
+                .iter()
+                .find(|e| e.path.ends_with(path))
+                .map(|e| e.reason.clone())
+                .unwrap_or_else(|| panic!("{} not reported", path))
+        };
+        assert!(reason("build/").ends_with(".gitignore:2 (build/)"));
+        assert!(reason("src/debug.log").ends_with(".gitignore:3 (*.log)"));
+        assert_eq!(
+            reason("src/notes.txt"),
+            "not matched by any --filters pattern"
+        );
+        assert_eq!(
+            reason("src/generated.rs"),
+            "matches config ignores pattern '**/generated.rs'"
+        );
+        assert!(!excluded.iter().any(|e| e.path.contains("build/out")));
+
+        let report = format_report(&excluded, files.len());
+        assert!(report.starts_with("Exclusion report: 1 files included"));
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use crate::core::file::FileData;
+use crate::error::{Error, Result};
+use crate::utils::exclusion::Exclusion;
 
 pub fn apply_budget_filters(
     files: Vec<FileData>,
@@ -6,18 +8,234 @@ pub fn apply_budget_filters(
     max: usize,
     limit_high: bool,
     limit_low: bool,
+    include_empty: bool,
 ) -> Vec<FileData> {
     files
         .into_iter()
-        .filter(|fd| {
-            // Always skip files with 0 tokens
-            if fd.tokens == 0 {
-                return false;
-            }
+        .filter(|fd| passes_budget(fd, min, max, limit_high, limit_low, include_empty))
+        .collect()
+}
+
+/// Apply the budget rules to a cost per file (one entry of `costs` per file,
+/// such as its rendered size) instead of each file's raw token count.
+/// Files without any tokens are still skipped, unless `include_empty` is set.
+pub fn apply_budget_filters_with_costs(
+    files: Vec<FileData>,
+    costs: &[usize],
+    min: usize,
+    max: usize,
+    limit_high: bool,
+    limit_low: bool,
+    include_empty: bool,
+) -> Vec<FileData> {
+    files
+        .into_iter()
+        .zip(costs)
+        .filter(|(fd, &cost)| {
+            keeps_content(fd, include_empty) && within_budget(cost, min, max, limit_high, limit_low)
+        })
+        .map(|(fd, _)| fd)
+        .collect()
+}
+
+/// The number of files each budget rule rejects, counted whether or not
+/// the rule is enabled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExclusions {
+    /// Files that could not be read, which are always skipped.
+    pub unreadable: usize,
+    /// Files without any tokens, which are skipped unless empty files are included.
+    pub empty: usize,
+    /// Files with fewer tokens than the minimum budget.
+    pub below_min: usize,
+    /// Files with more tokens than the maximum budget.
+    pub above_max: usize,
+}
 
-            let passes_low = if limit_low { fd.tokens >= min } else { true };
-            let passes_high = if limit_high { fd.tokens <= max } else { true };
-            passes_low && passes_high
+/// Count the files that each budget rule of [`apply_budget_filters`] excludes.
+pub fn count_exclusions(files: &[FileData], min: usize, max: usize) -> BudgetExclusions {
+    let mut exclusions = BudgetExclusions::default();
+    for fd in files {
+        if fd.error.is_some() {
+            exclusions.unreadable += 1;
+            continue;
+        }
+        if fd.tokens == 0 {
+            exclusions.empty += 1;
+            continue;
+        }
+        if fd.tokens < min {
+            exclusions.below_min += 1;
+        }
+        if fd.tokens > max {
+            exclusions.above_max += 1;
+        }
+    }
+    exclusions
+}
+
+/// Describe, for every file that the budget rules of
+/// [`apply_budget_filters_with_costs`] exclude, the rule that excluded it:
+/// a read error, empty content, or the budget bound its cost is outside of.
+pub fn budget_exclusions(
+    files: &[FileData],
+    costs: &[usize],
+    min: usize,
+    max: usize,
+    limit_high: bool,
+    limit_low: bool,
+    include_empty: bool,
+) -> Vec<Exclusion> {
+    files
+        .iter()
+        .zip(costs)
+        .filter_map(|(fd, &cost)| {
+            let reason = if let Some(error) = &fd.error {
+                format!("read error: {}", error)
+            } else if fd.tokens == 0 && !include_empty {
+                "empty content".to_string()
+            } else if limit_low && cost < min {
+                format!("{} tokens, below --min-budget {}", cost, min)
+            } else if limit_high && cost > max {
+                format!("{} tokens, above --max-budget {}", cost, max)
+            } else {
+                return None;
+            };
+            Some(Exclusion {
+                path: fd.path.clone(),
+                reason,
+            })
         })
         .collect()
 }
+
+/// Check a single file against the budget rules used by [`apply_budget_filters`].
+pub fn passes_budget(
+    fd: &FileData,
+    min: usize,
+    max: usize,
+    limit_high: bool,
+    limit_low: bool,
+    include_empty: bool,
+) -> bool {
+    keeps_content(fd, include_empty) && within_budget(fd.tokens, min, max, limit_high, limit_low)
+}
+
+/// Files that could not be read are always skipped, and files with 0 tokens
+/// unless `include_empty` is set.
+fn keeps_content(fd: &FileData, include_empty: bool) -> bool {
+    fd.error.is_none() && (fd.tokens > 0 || include_empty)
+}
+
+fn within_budget(tokens: usize, min: usize, max: usize, limit_high: bool, limit_low: bool) -> bool {
+    let passes_low = if limit_low { tokens >= min } else { true };
+    let passes_high = if limit_high { tokens <= max } else { true };
+    passes_low && passes_high
+}
+
+/// With `--fail-on-error`, fail when any file could not be read.
+pub fn enforce_readable(errors: &[FileData], fail_on_error: bool) -> Result<()> {
+    if fail_on_error && !errors.is_empty() {
+        return Err(Error::Read(format!(
+            "{} files could not be read",
+            errors.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    fn files() -> Vec<FileData> {
+        let encoding = Encoding::default();
+        vec![
+            FileData::new("small.rs", "fn a() {}", &encoding),
+            FileData::new("large.rs", "fn b() { let value = 1 + 2 + 3; }", &encoding),
+            FileData::new("empty.rs", "  \n", &encoding),
+            FileData::with_error("broken.rs", "permission denied"),
+        ]
+    }
+
+    fn paths(files: &[FileData]) -> Vec<&str> {
+        files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_apply_budget_filters() {
+        let files = files();
+        let max = files[0].tokens;
+
+        let kept = apply_budget_filters(files.clone(), 0, max, true, false, false);
+        assert_eq!(paths(&kept), ["small.rs"]);
+        let kept = apply_budget_filters(files.clone(), max + 1, 0, false, true, false);
+        assert_eq!(paths(&kept), ["large.rs"]);
+        let kept = apply_budget_filters(files, 0, 0, false, false, false);
+        assert_eq!(paths(&kept), ["small.rs", "large.rs"]);
+    }
+
+    #[test]
+    fn test_include_empty_keeps_empty_but_not_unreadable_files() {
+        let kept = apply_budget_filters(files(), 0, 0, false, false, true);
+        assert_eq!(paths(&kept), ["small.rs", "large.rs", "empty.rs"]);
+
+        // A 0-token file still has to pass the minimum budget when it is set.
+        let kept = apply_budget_filters(files(), 1, 0, false, true, true);
+        assert_eq!(paths(&kept), ["small.rs", "large.rs"]);
+
+        let costs = [10, 20, 0, 0];
+        let kept = apply_budget_filters_with_costs(files(), &costs, 0, 15, true, false, true);
+        assert_eq!(paths(&kept), ["small.rs", "empty.rs"]);
+    }
+
+    #[test]
+    fn test_budget_exclusions() {
+        let files = files();
+        let costs = [10, 20, 0, 0];
+
+        let excluded = budget_exclusions(&files, &costs, 15, 0, false, true, false);
+        let reasons: Vec<(&str, &str)> = excluded
+            .iter()
+            .map(|e| (e.path.as_str(), e.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("small.rs", "10 tokens, below --min-budget 15"),
+                ("empty.rs", "empty content"),
+                ("broken.rs", "read error: permission denied"),
+            ]
+        );
+
+        // With empty files included, only the read error remains.
+        let excluded = budget_exclusions(&files, &costs, 0, 15, false, false, true);
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].path, "broken.rs");
+    }
+
+    #[test]
+    fn test_count_exclusions() {
+        let files = files();
+        let exclusions = count_exclusions(&files, files[1].tokens, files[0].tokens);
+        assert_eq!(
+            exclusions,
+            BudgetExclusions {
+                unreadable: 1,
+                empty: 1,
+                below_min: 1,
+                above_max: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_enforce_readable() {
+        let errors = vec![FileData::with_error("broken.rs", "permission denied")];
+        assert!(enforce_readable(&errors, false).is_ok());
+        assert!(enforce_readable(&[], true).is_ok());
+        let error = enforce_readable(&errors, true).unwrap_err();
+        assert_eq!(error.to_string(), "Read error: 1 files could not be read");
+    }
+}
@@ -11,7 +11,7 @@ use std::io::IsTerminal;
 use std::path::Path;
 
 /// Build a GlobSet from a list of string patterns
-fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
     let mut builder = GlobSetBuilder::new();
     for pattern in patterns {
         let glob = Glob::new(pattern).map_err(|e| {
@@ -24,6 +24,15 @@ fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
         .map_err(|e| Error::Filter(format!("Failed to build glob set: {}", e)))
 }
 
+/// The directory walk used to find files: respects .gitignore and the other
+/// standard ignore files, but leaves hidden files to the globs.
+pub(crate) fn walk_builder(root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder.standard_filters(true); // Respect .gitignore, etc.
+    builder.hidden(false); // Do not skip hidden files by default, let globs decide
+    builder
+}
+
 /// Find files using the 'ignore' crate for fast, correct directory traversal
 pub fn find_files(
     root: &Path,
@@ -35,11 +44,7 @@ pub fn find_files(
 
     let (tx, rx) = std::sync::mpsc::channel();
     
-    let mut builder = WalkBuilder::new(root);
-    builder.standard_filters(true); // Respect .gitignore, etc.
-    builder.hidden(false); // Do not skip hidden files by default, let globs decide
-    
-    let walker = builder.build_parallel();
+    let walker = walk_builder(root).build_parallel();
     
     walker.run(|| {
         let tx = tx.clone();
@@ -68,6 +73,21 @@ pub fn find_files(
     Ok(files)
 }
 
+/// The part of a found `path` below the scan `root`, as the globs see it.
+///
+/// Paths outside `root`, such as names read from stdin, only lose a leading
+/// `./`, and a `root` naming a single file leaves its file name.
+pub fn relative_path<'a>(path: &'a str, root: &Path) -> &'a str {
+    let relative = match Path::new(path).strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => Path::new(path).file_name(),
+        Ok(relative) => Some(relative.as_os_str()),
+        Err(_) => None,
+    };
+    relative
+        .and_then(|relative| relative.to_str())
+        .unwrap_or_else(|| path.strip_prefix("./").unwrap_or(path))
+}
+
 /// Read file names from standard input
 pub fn read_from_stdin() -> Result<Vec<String>> {
     use std::io::{self, BufRead};
@@ -137,7 +157,18 @@ mod tests {
     fn test_read_from_stdin() {
         // Note: Testing stdin requires more complex setup with mock stdin
         // This would be implementation-specific based on how you want to test stdin
-        assert_eq!(has_stdin_pipe(), true);
+        assert!(has_stdin_pipe());
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("./src/main.rs", Path::new(".")), "src/main.rs");
+        assert_eq!(relative_path("src/main.rs", Path::new(".")), "src/main.rs");
+        assert_eq!(relative_path("src/core/file.rs", Path::new("src")), "core/file.rs");
+        assert_eq!(relative_path("/abs/root/lib.rs", Path::new("/abs/root/")), "lib.rs");
+        assert_eq!(relative_path("../x/src/a.rs", Path::new("../x")), "src/a.rs");
+        assert_eq!(relative_path("src/main.rs", Path::new("src/main.rs")), "main.rs");
+        assert_eq!(relative_path("other/a.rs", Path::new("src")), "other/a.rs");
     }
 
     #[test]
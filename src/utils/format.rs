@@ -1,30 +1,111 @@
 //! Output formatting functionality.
 //!
-//! Handles taking processed file data and formatting it as plain text
-//! or JSON, either printing it to stdout or saving it to a file.
+//! Handles taking processed file data and formatting it as plain text,
+//! Markdown, XML or JSON, either printing it to stdout or saving it to a file.
+//! JSON Lines output is streamed record by record through [`JsonlWriter`].
 
+use super::language::detect_language;
 use crate::core::file::FileData;
+use crate::core::tokenizer::{Encoding, Tokenizer};
+use crate::core::tokens;
+use crate::core::tree;
 use anyhow::Result;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-/// Output the final processed file data to the console or a file.
-///
-/// # Arguments
-///
-/// * `files` - A slice of `FileData` representing the files to output.
-/// * `format` - A string defining the output format (e.g., "text", "json").
-/// * `output` - An optional path to a file where the results should be written.
-///
-/// # Returns
-///
-/// * `Result<()>` - Returns success if writing completes, or an error.
-pub fn output_results(files: &[FileData], format: &str, output: Option<PathBuf>) -> Result<()> {
-    let content = match format {
-        "text" => format_text(files),
-        "json" => serde_json::to_string_pretty(files)?,
+/// Settings for rendering merged output.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputOptions<'a> {
+    /// Include the directory tree of the selected files.
+    pub with_tree: bool,
+    /// Include the token statistics board of the selected files.
+    pub with_stats: bool,
+    /// Render `json` output as an object recording the tokenizer, even
+    /// without a preamble.
+    pub with_meta: bool,
+    /// The tokenizer the token counts were computed with.
+    pub tokenizer: &'a dyn Tokenizer,
+    /// The files that could not be read, listed under `errors` in the `json` format.
+    pub errors: &'a [FileData],
+}
+
+impl Default for OutputOptions<'_> {
+    fn default() -> Self {
+        Self {
+            with_tree: false,
+            with_stats: false,
+            with_meta: false,
+            tokenizer: &Encoding::Cl100k,
+            errors: &[],
+        }
+    }
+}
+
+/// Render the processed file data in the given format.
+pub fn render(files: &[FileData], format: &str, options: &OutputOptions) -> Result<String> {
+    if (options.with_tree || options.with_stats) && !matches!(format, "text" | "markdown" | "json")
+    {
+        return Err(anyhow::anyhow!(
+            "The tree and stats preamble is not supported by the {} format",
+            format
+        ));
+    }
+
+    Ok(match format {
+        "text" => format_text_preamble(files, options) + &format_text(files),
+        "markdown" => format_markdown_preamble(files, options) + &format_markdown(files),
+        "xml" => format_xml(files, options),
+        "json" => format_json(files, options)?,
         _ => return Err(anyhow::anyhow!("Unsupported format: {}", format)),
+    })
+}
+
+/// Count the tokens a single file adds to output rendered in `format`: its
+/// content plus the header, fence or XML wrapper around it, without the
+/// framing shared by the whole document.
+pub fn rendered_file_cost(
+    file: &FileData,
+    format: &str,
+    tokenizer: &dyn Tokenizer,
+) -> Result<usize> {
+    let options = OutputOptions {
+        tokenizer,
+        ..OutputOptions::default()
+    };
+    let framing = tokenizer.count(&render(&[], format, &options)?);
+    let rendered = tokenizer.count(&render(std::slice::from_ref(file), format, &options)?);
+    Ok(rendered.saturating_sub(framing))
+}
+
+/// Count the tokens of output rendered in `format` that belong to no single
+/// file: the framing of the document plus, as set in `options`, the tree and
+/// stats preamble describing `files`.
+pub fn shared_cost(files: &[FileData], format: &str, options: &OutputOptions) -> Result<usize> {
+    let plain = OutputOptions {
+        tokenizer: options.tokenizer,
+        ..OutputOptions::default()
     };
+    let framing = options.tokenizer.count(&render(&[], format, &plain)?);
+    // The preamble only depends on paths and token counts, so measure it on
+    // content-less copies instead of rendering every file twice.
+    let outlines: Vec<FileData> = files
+        .iter()
+        .map(|f| FileData {
+            content: String::new(),
+            ..f.clone()
+        })
+        .collect();
+    let preamble = options
+        .tokenizer
+        .count(&render(&outlines, format, options)?)
+        .saturating_sub(options.tokenizer.count(&render(&outlines, format, &plain)?));
+    Ok(framing + preamble)
+}
 
+/// Write already rendered output to the given file, or print it to stdout.
+pub fn write_output(content: &str, output: Option<PathBuf>) -> Result<()> {
     match output {
         Some(path) => {
             std::fs::write(path, content)?;
@@ -37,10 +118,552 @@ pub fn output_results(files: &[FileData], format: &str, output: Option<PathBuf>)
     Ok(())
 }
 
+/// Open the destination for streamed output: the given file, or stdout.
+pub fn open_output(output: Option<PathBuf>) -> Result<Box<dyn Write + Send>> {
+    Ok(match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+/// A single JSON Lines record describing one file.
+#[derive(Serialize)]
+struct JsonlFileRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    path: &'a str,
+    tokens: usize,
+    bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// The trailing JSON Lines record summarizing everything written before it.
+#[derive(Serialize)]
+struct JsonlSummaryRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    tokenizer: &'a str,
+    estimated: bool,
+    files: usize,
+    tokens: usize,
+    bytes: usize,
+}
+
+/// Streams `FileData` as JSON Lines: one `file` record per line, followed by
+/// a final `summary` record written by [`JsonlWriter::finish`].
+///
+/// Write errors are remembered rather than returned from `write_file`, so the
+/// writer can be fed from processing callbacks; `finish` reports the first one.
+pub struct JsonlWriter {
+    out: Box<dyn Write + Send>,
+    include_content: bool,
+    tokenizer: String,
+    estimated: bool,
+    files: usize,
+    tokens: usize,
+    bytes: usize,
+    error: Option<io::Error>,
+}
+
+impl JsonlWriter {
+    /// Create a writer; `include_content` controls whether file contents are emitted,
+    /// and the name of `tokenizer` is reported in the summary record.
+    pub fn new(
+        out: Box<dyn Write + Send>,
+        include_content: bool,
+        tokenizer: &dyn Tokenizer,
+    ) -> Self {
+        Self {
+            out,
+            include_content,
+            tokenizer: tokenizer.name().to_string(),
+            estimated: tokenizer.is_estimate(),
+            files: 0,
+            tokens: 0,
+            bytes: 0,
+            error: None,
+        }
+    }
+
+    /// Write one `file` record and flush it, so consumers see it immediately.
+    pub fn write_file(&mut self, file: &FileData) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let record = JsonlFileRecord {
+            kind: "file",
+            path: &file.path,
+            tokens: file.tokens,
+            bytes: file.content.len(),
+            extension: Path::new(&file.path).extension().and_then(|e| e.to_str()),
+            content: self.include_content.then_some(file.content.as_str()),
+            error: file.error.as_deref(),
+        };
+
+        self.files += 1;
+        self.tokens += file.tokens;
+        self.bytes += file.content.len();
+
+        if let Err(e) = write_jsonl_line(&mut self.out, &record) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Write the trailing `summary` record and flush the output.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(e) = self.error {
+            return Err(e.into());
+        }
+
+        let summary = JsonlSummaryRecord {
+            kind: "summary",
+            tokenizer: &self.tokenizer,
+            estimated: self.estimated,
+            files: self.files,
+            tokens: self.tokens,
+            bytes: self.bytes,
+        };
+        write_jsonl_line(&mut self.out, &summary)?;
+        Ok(())
+    }
+}
+
+fn write_jsonl_line(out: &mut dyn Write, record: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+fn format_text_preamble(files: &[FileData], options: &OutputOptions) -> String {
+    let mut output = String::new();
+    if options.with_tree {
+        output.push_str(if options.tokenizer.is_estimate() {
+            "=== Tree (estimated tokens) ===\n"
+        } else {
+            "=== Tree ===\n"
+        });
+        output.push_str(&tree::format_tree(&tree::build_tree(files), "", true));
+        output.push('\n');
+    }
+    if options.with_stats {
+        output.push_str("=== Stats ===");
+        output.push_str(&tokens::format_token_board(
+            files,
+            files.len(),
+            options.tokenizer,
+            None,
+            None,
+        ));
+        output.push('\n');
+    }
+    output
+}
+
+fn format_markdown_preamble(files: &[FileData], options: &OutputOptions) -> String {
+    let mut output = String::new();
+    if options.with_tree {
+        let tree = tree::format_tree(&tree::build_tree(files), "", true);
+        let heading = if options.tokenizer.is_estimate() {
+            "## Tree (estimated tokens)"
+        } else {
+            "## Tree"
+        };
+        output.push_str(&format!("{}\n\n```text\n{}```\n\n", heading, tree));
+    }
+    if options.with_stats {
+        let board = tokens::format_token_board(files, files.len(), options.tokenizer, None, None);
+        output.push_str(&format!(
+            "## Token Statistics\n\n```text\n{}```\n\n",
+            board.trim_start()
+        ));
+    }
+    output
+}
+
+/// Serialize the files as a JSON array, each record naming the tokenizer,
+/// with the files that could not be read at the end, or as an object carrying
+/// the tokenizer, the requested preamble sections and the read errors next to
+/// the files.
+fn format_json(files: &[FileData], options: &OutputOptions) -> Result<String> {
+    if !options.with_tree && !options.with_stats && !options.with_meta {
+        let records = files
+            .iter()
+            .chain(options.errors)
+            .map(|file| {
+                let mut record = serde_json::to_value(file)?;
+                record["tokenizer"] = serde_json::Value::from(options.tokenizer.name());
+                Ok(record)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(serde_json::to_string_pretty(&records)?);
+    }
+
+    let mut bundle = serde_json::Map::new();
+    bundle.insert(
+        "tokenizer".to_string(),
+        serde_json::Value::from(options.tokenizer.name()),
+    );
+    bundle.insert(
+        "estimated".to_string(),
+        serde_json::Value::from(options.tokenizer.is_estimate()),
+    );
+    if options.with_tree {
+        bundle.insert(
+            "tree".to_string(),
+            serde_json::to_value(tree::build_tree(files))?,
+        );
+    }
+    if options.with_stats {
+        bundle.insert(
+            "stats".to_string(),
+            tokens::token_json_value(files, files.len(), options.tokenizer, None, None, None),
+        );
+    }
+    bundle.insert("files".to_string(), serde_json::to_value(files)?);
+    if !options.errors.is_empty() {
+        bundle.insert("errors".to_string(), errors_json(options.errors));
+    }
+
+    Ok(serde_json::to_string_pretty(&bundle)?)
+}
+
+/// List the files that could not be read as `{"path", "error"}` objects.
+pub fn errors_json(errors: &[FileData]) -> serde_json::Value {
+    errors
+        .iter()
+        .map(|f| serde_json::json!({ "path": f.path, "error": f.error }))
+        .collect()
+}
+
+/// Whether `content` needs a newline added to end on a line of its own.
+fn lacks_final_newline(content: &str) -> bool {
+    !content.is_empty() && !content.ends_with('\n')
+}
+
 fn format_text(files: &[FileData]) -> String {
     let mut output = String::from("=== Result ===\n");
     for file in files {
         output.push_str(&format!("File: {}\n{}", file.path, file.content));
+        // Keep the next `File:` header on its own line.
+        if lacks_final_newline(&file.content) {
+            output.push('\n');
+        }
+    }
+    output
+}
+
+fn format_markdown(files: &[FileData]) -> String {
+    let mut output = String::new();
+    for file in files {
+        let fence = code_fence(&file.content);
+        let language = detect_language(&file.path).unwrap_or("");
+
+        output.push_str(&format!("## {}\n\n", file.path));
+        output.push_str(&format!("{}{}\n", fence, language));
+        output.push_str(&file.content);
+        if lacks_final_newline(&file.content) {
+            output.push('\n');
+        }
+        output.push_str(&format!("{}\n\n", fence));
     }
     output
 }
+
+/// Build a backtick fence that is longer than any backtick run inside `content`,
+/// so the content can never close the code block early.
+fn code_fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in content.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+/// Wrap each file in a `<document>` element, followed by the files that could
+/// not be read, which carry their error instead of a token count and content.
+fn format_xml(files: &[FileData], options: &OutputOptions) -> String {
+    let mut output = String::from("<documents>\n");
+    for (i, file) in files.iter().chain(options.errors).enumerate() {
+        output.push_str(&format!("<document index=\"{}\"", i + 1));
+        match &file.error {
+            Some(error) => output.push_str(&format!(" error=\"{}\"", escape_xml(error))),
+            None => {
+                output.push_str(&format!(" tokens=\"{}\"", file.tokens));
+                if options.tokenizer.is_estimate() {
+                    output.push_str(" estimated=\"true\"");
+                }
+            }
+        }
+        output.push_str(">\n");
+        output.push_str(&format!("<source>{}</source>\n", escape_xml(&file.path)));
+        if file.error.is_none() {
+            output.push_str(&format!(
+                "<document_content>{}</document_content>\n",
+                escape_xml(&file.content)
+            ));
+        }
+        output.push_str("</document>\n");
+    }
+    output.push_str("</documents>");
+    output
+}
+
+/// Escape the characters that are significant in XML text and attribute values.
+pub fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_fence_length() {
+        assert_eq!(code_fence("plain"), "```");
+        assert_eq!(code_fence("inline `code`"), "```");
+        assert_eq!(code_fence("```rust\nfn main() {}\n```"), "````");
+        assert_eq!(code_fence("`````"), "``````");
+    }
+
+    #[test]
+    fn test_markdown_formatting() {
+        let files = vec![
+            FileData::new("src/main.rs", "fn main() {}", &Encoding::default()),
+            FileData::new("README.md", "```sh\ncargo run\n```\n", &Encoding::default()),
+        ];
+
+        let markdown = format_markdown(&files);
+        assert!(markdown.contains("## src/main.rs\n\n```rust\nfn main() {}\n```\n"));
+        assert!(markdown.contains("## README.md\n\n````markdown\n```sh\ncargo run\n```\n````\n"));
+    }
+
+    #[test]
+    fn test_xml_formatting() {
+        let files = vec![
+            FileData::new(
+                "src/a&b.rs",
+                "if a < b && c > d { \"]]>\" }",
+                &Encoding::default(),
+            ),
+            FileData::with_error("broken.bin", "Invalid UTF-8: <bad>"),
+        ];
+
+        let xml = format_xml(&files, &OutputOptions::default());
+        assert!(xml.starts_with("<documents>\n<document index=\"1\" tokens=\""));
+        assert!(xml.contains("<source>src/a&amp;b.rs</source>"));
+        assert!(xml.contains(
+            "<document_content>if a &lt; b &amp;&amp; c &gt; d { &quot;]]&gt;&quot; }</document_content>"
+        ));
+        assert!(xml.contains(
+            "<document index=\"2\" error=\"Invalid UTF-8: &lt;bad&gt;\">\n<source>broken.bin</source>\n</document>"
+        ));
+        assert!(xml.ends_with("</documents>"));
+    }
+
+    #[test]
+    fn test_xml_lists_read_errors() {
+        let tokenizer = Encoding::default();
+        let files = vec![FileData::new("src/main.rs", "fn main() {}", &tokenizer)];
+        let errors = vec![FileData::with_error("data.bin", "Invalid UTF-8 & more")];
+        let options = OutputOptions {
+            tokenizer: &tokenizer,
+            errors: &errors,
+            ..OutputOptions::default()
+        };
+
+        let xml = render(&files, "xml", &options).unwrap();
+        assert!(xml.contains(
+            "<document index=\"2\" error=\"Invalid UTF-8 &amp; more\">\n<source>data.bin</source>\n</document>\n</documents>"
+        ));
+    }
+
+    #[test]
+    fn test_jsonl_writer() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("out.jsonl");
+
+        let mut writer = JsonlWriter::new(
+            open_output(Some(path.clone())).unwrap(),
+            true,
+            &Encoding::default(),
+        );
+        writer.write_file(&FileData::new(
+            "src/main.rs",
+            "fn main() {}",
+            &Encoding::default(),
+        ));
+        writer.write_file(&FileData::with_error("broken", "Invalid UTF-8"));
+        writer.finish().unwrap();
+
+        let written = std::fs::read_to_string(path).unwrap();
+        let records: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["type"], "file");
+        assert_eq!(records[0]["extension"], "rs");
+        assert_eq!(records[0]["bytes"], 12);
+        assert_eq!(records[0]["content"], "fn main() {}");
+        assert_eq!(records[1]["error"], "Invalid UTF-8");
+        assert_eq!(records[2]["type"], "summary");
+        assert_eq!(records[2]["tokenizer"], "cl100k_base");
+        assert_eq!(records[2]["files"], 2);
+        assert_eq!(records[2]["tokens"], records[0]["tokens"]);
+    }
+
+    #[test]
+    fn test_preamble_sections() {
+        let files = vec![
+            FileData::new("src/main.rs", "fn main() {}", &Encoding::default()),
+            FileData::new("src/lib.rs", "pub fn lib() {}", &Encoding::default()),
+        ];
+        let options = OutputOptions {
+            with_tree: true,
+            with_stats: true,
+            with_meta: false,
+            tokenizer: &Encoding::default(),
+            errors: &[],
+        };
+
+        let text = render(&files, "text", &options).unwrap();
+        let tree_at = text.find("=== Tree ===").unwrap();
+        let stats_at = text.find("=== Stats ===").unwrap();
+        let result_at = text.find("=== Result ===").unwrap();
+        assert!(tree_at < stats_at && stats_at < result_at);
+        assert!(text.contains("main.rs ("));
+
+        let markdown = render(&files, "markdown", &options).unwrap();
+        assert!(markdown.starts_with("## Tree\n\n```text\n"));
+        assert!(markdown.contains("## Token Statistics"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&files, "json", &options).unwrap()).unwrap();
+        assert_eq!(json["tokenizer"], "cl100k_base");
+        assert_eq!(json["tree"]["children"][0]["path"], "src");
+        assert_eq!(json["stats"]["results"].as_array().unwrap().len(), 2);
+        assert_eq!(json["files"].as_array().unwrap().len(), 2);
+
+        assert!(render(&files, "xml", &options).is_err());
+    }
+
+    #[test]
+    fn test_json_lists_read_errors() {
+        let files = vec![FileData::new(
+            "src/main.rs",
+            "fn main() {}",
+            &Encoding::default(),
+        )];
+        let errors = vec![FileData::with_error("data.bin", "Invalid UTF-8")];
+        let options = OutputOptions {
+            errors: &errors,
+            ..OutputOptions::default()
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&files, "json", &options).unwrap()).unwrap();
+        assert_eq!(json[1]["path"], "data.bin");
+        assert_eq!(json[1]["error"], "Invalid UTF-8");
+        assert_eq!(json[0]["tokenizer"], "cl100k_base");
+        assert_eq!(json[1]["tokenizer"], "cl100k_base");
+
+        let options = OutputOptions {
+            with_meta: true,
+            ..options
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&files, "json", &options).unwrap()).unwrap();
+        assert_eq!(json["errors"][0]["path"], "data.bin");
+        assert_eq!(json["errors"][0]["error"], "Invalid UTF-8");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&files, "json", &OutputOptions::default()).unwrap())
+                .unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["path"], "src/main.rs");
+
+        let options = OutputOptions {
+            tokenizer: &Encoding::O200k,
+            ..OutputOptions::default()
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&files, "json", &options).unwrap()).unwrap();
+        assert_eq!(json[0]["tokenizer"], "o200k_base");
+    }
+
+    #[test]
+    fn test_rendered_file_cost() {
+        let tokenizer = Encoding::default();
+        let file = FileData::new("src/main.rs", "fn main() {}\n", &tokenizer);
+
+        let text = rendered_file_cost(&file, "text", &tokenizer).unwrap();
+        let xml = rendered_file_cost(&file, "xml", &tokenizer).unwrap();
+        assert!(text > file.tokens);
+        assert!(xml > text);
+        assert_eq!(
+            text,
+            tokens::count_tokens("File: src/main.rs\nfn main() {}\n", &tokenizer)
+        );
+    }
+
+    #[test]
+    fn test_shared_cost() {
+        let tokenizer = Encoding::default();
+        let files = vec![
+            FileData::new("src/main.rs", "fn main() {}\n", &tokenizer),
+            FileData::new("src/lib.rs", "pub fn lib() {}\n", &tokenizer),
+        ];
+        let plain = OutputOptions {
+            tokenizer: &tokenizer,
+            ..OutputOptions::default()
+        };
+        let with_tree = OutputOptions {
+            with_tree: true,
+            ..plain
+        };
+
+        let framing = shared_cost(&files, "xml", &plain).unwrap();
+        assert_eq!(
+            framing,
+            tokenizer.count(&render(&[], "xml", &plain).unwrap())
+        );
+        assert!(framing > 0);
+        let preamble = shared_cost(&files, "markdown", &with_tree).unwrap();
+        assert_eq!(
+            preamble,
+            tokenizer.count(&format_markdown_preamble(&files, &with_tree))
+        );
+
+        // The shared cost and the cost of each file add up to the document.
+        let rendered = render(&files, "markdown", &with_tree).unwrap();
+        let costs: usize = files
+            .iter()
+            .map(|f| rendered_file_cost(f, "markdown", &tokenizer).unwrap())
+            .sum();
+        assert!((tokenizer.count(&rendered) as i64 - (preamble + costs) as i64).abs() <= 2);
+    }
+}
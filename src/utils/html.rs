@@ -0,0 +1,310 @@
+//! Self-contained HTML reports for the tree and tokens commands.
+//!
+//! The report contains a collapsible directory tree, a sortable token table
+//! and a treemap of token weight per directory. Everything (styles, script
+//! and the SVG treemap) is inlined, so the file can be attached anywhere.
+
+use super::format::escape_xml as escape;
+use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
+use crate::core::tree::{self, TreeNode};
+
+const TREEMAP_WIDTH: f64 = 960.0;
+const TREEMAP_HEIGHT: f64 = 480.0;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2rem; color: #1f2328; }
+h1 { font-size: 1.5rem; } h2 { font-size: 1.2rem; margin-top: 2rem; }
+details { margin-left: 1.2rem; } summary { cursor: pointer; }
+.file { margin-left: 2.4rem; }
+.tokens { color: #656d76; font-variant-numeric: tabular-nums; }
+table { border-collapse: collapse; }
+th, td { padding: 0.25rem 0.75rem; border-bottom: 1px solid #d0d7de; text-align: left; }
+th { cursor: pointer; user-select: none; background: #f6f8fa; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+svg text { font-size: 11px; fill: #fff; pointer-events: none; }
+svg rect { stroke: #fff; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const body = th.closest("table").tBodies[0];
+    const numeric = th.dataset.type === "number";
+    const ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    const rows = Array.from(body.rows);
+    rows.sort((a, b) => {
+      const x = a.cells[column].dataset.value, y = b.cells[column].dataset.value;
+      const order = numeric ? Number(x) - Number(y) : x.localeCompare(y);
+      return ascending ? order : -order;
+    });
+    rows.forEach((row) => body.appendChild(row));
+  });
+});
+"#;
+
+/// Render an HTML report for the given files.
+///
+/// # Arguments
+///
+/// * `files` - The processed files to report on.
+/// * `title` - The report title.
+/// * `max_rows` - The maximum number of files listed in the token table.
+/// * `tokenizer` - The tokenizer the token counts were computed with.
+///
+/// # Returns
+///
+/// * `String` - A complete HTML document.
+pub fn format_html_report(
+    files: &[FileData],
+    title: &str,
+    max_rows: usize,
+    tokenizer: &dyn Tokenizer,
+) -> String {
+    let root = tree::build_tree(files);
+    let total: usize = files.iter().map(|f| f.tokens).sum();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n", escape(title)));
+    html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+    html.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+    if tokenizer.is_estimate() {
+        html.push_str(&format!(
+            "<p>{} files, ~{} tokens (estimated)</p>\n",
+            files.len(),
+            total
+        ));
+    } else {
+        html.push_str(&format!(
+            "<p>{} files, {} tokens ({})</p>\n",
+            files.len(),
+            total,
+            escape(tokenizer.name())
+        ));
+    }
+
+    html.push_str("<h2>Directory tree</h2>\n");
+    for child in &root.children {
+        render_tree_node(&mut html, child);
+    }
+
+    html.push_str("<h2>Token weight per directory</h2>\n");
+    html.push_str(&render_treemap(&root));
+
+    html.push_str("<h2>Files</h2>\n");
+    html.push_str(&render_table(files, total, max_rows));
+
+    html.push_str(&format!("<script>{}</script>\n</body>\n</html>\n", SCRIPT));
+    html
+}
+
+fn render_tree_node(html: &mut String, node: &TreeNode) {
+    if node.children.is_empty() {
+        html.push_str(&format!(
+            "<div class=\"file\">{} <span class=\"tokens\">({} tokens)</span></div>\n",
+            escape(&node.path),
+            node.tokens
+        ));
+        return;
+    }
+
+    html.push_str(&format!(
+        "<details open><summary>{}/ <span class=\"tokens\">({} tokens)</span></summary>\n",
+        escape(&node.path),
+        node.tokens
+    ));
+    for child in &node.children {
+        render_tree_node(html, child);
+    }
+    html.push_str("</details>\n");
+}
+
+fn render_table(files: &[FileData], total: usize, max_rows: usize) -> String {
+    let mut sorted: Vec<&FileData> = files.iter().collect();
+    sorted.sort_by_key(|f| std::cmp::Reverse(f.tokens));
+
+    let mut html = String::from(
+        "<table class=\"sortable\">\n<thead><tr><th data-type=\"text\">Path</th>\
+         <th data-type=\"number\">Tokens</th><th data-type=\"number\">Share</th></tr></thead>\n<tbody>\n",
+    );
+    for file in sorted.into_iter().take(max_rows) {
+        let share = if total == 0 {
+            0.0
+        } else {
+            file.tokens as f64 * 100.0 / total as f64
+        };
+        html.push_str(&format!(
+            "<tr><td data-value=\"{path}\">{path}</td>\
+             <td class=\"num\" data-value=\"{tokens}\">{tokens}</td>\
+             <td class=\"num\" data-value=\"{share:.4}\">{share:.1}%</td></tr>\n",
+            path = escape(&file.path),
+            tokens = file.tokens,
+            share = share
+        ));
+    }
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+/// A positioned rectangle in the treemap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+fn render_treemap(root: &TreeNode) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = TREEMAP_WIDTH,
+        h = TREEMAP_HEIGHT
+    );
+
+    let bounds = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: TREEMAP_WIDTH,
+        h: TREEMAP_HEIGHT,
+    };
+    for (child, rect) in layout(&root.children, bounds) {
+        let hue = color_hue(&child.path);
+        render_treemap_node(&mut svg, child, &child.path, rect, hue);
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn render_treemap_node(svg: &mut String, node: &TreeNode, path: &str, rect: Rect, hue: u32) {
+    if node.children.is_empty() {
+        svg.push_str(&format!(
+            "<g><title>{} ({} tokens)</title><rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"hsl({}, 55%, 45%)\"/>",
+            escape(path),
+            node.tokens,
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+            hue
+        ));
+        if rect.w > 60.0 && rect.h > 14.0 {
+            svg.push_str(&format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                rect.x + 3.0,
+                rect.y + 12.0,
+                escape(&node.path)
+            ));
+        }
+        svg.push_str("</g>\n");
+        return;
+    }
+
+    for (child, child_rect) in layout(&node.children, rect) {
+        let child_path = format!("{}/{}", path, child.path);
+        render_treemap_node(svg, child, &child_path, child_rect, hue);
+    }
+}
+
+/// Slice `bounds` along its longer side into one rectangle per node with tokens,
+/// proportional to each node's token count.
+fn layout(nodes: &[TreeNode], bounds: Rect) -> Vec<(&TreeNode, Rect)> {
+    let mut weighted: Vec<&TreeNode> = nodes.iter().filter(|n| n.tokens > 0).collect();
+    weighted.sort_by_key(|n| std::cmp::Reverse(n.tokens));
+    let total: usize = weighted.iter().map(|n| n.tokens).sum();
+    if total == 0 {
+        return Vec::new();
+    }
+
+    let horizontal = bounds.w >= bounds.h;
+    let mut offset = 0.0;
+    weighted
+        .into_iter()
+        .map(|node| {
+            let fraction = node.tokens as f64 / total as f64;
+            let rect = if horizontal {
+                let w = bounds.w * fraction;
+                let rect = Rect {
+                    x: bounds.x + offset,
+                    y: bounds.y,
+                    w,
+                    h: bounds.h,
+                };
+                offset += w;
+                rect
+            } else {
+                let h = bounds.h * fraction;
+                let rect = Rect {
+                    x: bounds.x,
+                    y: bounds.y + offset,
+                    w: bounds.w,
+                    h,
+                };
+                offset += h;
+                rect
+            };
+            (node, rect)
+        })
+        .collect()
+}
+
+/// Pick a stable hue for a top-level entry so its subtree shares one color.
+fn color_hue(name: &str) -> u32 {
+    name.bytes()
+        .fold(7u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32))
+        % 360
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    #[test]
+    fn test_html_report() {
+        let files = vec![
+            FileData::new(
+                "src/main.rs",
+                "fn main() { println!(\"<b>\"); }",
+                &Encoding::default(),
+            ),
+            FileData::new("src/lib.rs", "pub fn lib() {}", &Encoding::default()),
+            FileData::new("README.md", "# Read me", &Encoding::default()),
+        ];
+
+        let html = format_html_report(&files, "Report <test>", 10, &Encoding::default());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Report &lt;test&gt;</title>"));
+        assert!(html.contains("<details open><summary>src/"));
+        assert!(html.contains("<table class=\"sortable\">"));
+        assert_eq!(html.matches("<tr><td").count(), 3);
+        assert_eq!(html.matches("<rect ").count(), 3);
+        assert!(html.contains("<title>src/main.rs ("));
+    }
+
+    #[test]
+    fn test_layout_is_proportional() {
+        let mut big = TreeNode::new("big".to_string());
+        big.tokens = 300;
+        let mut small = TreeNode::new("small".to_string());
+        small.tokens = 100;
+        let empty = TreeNode::new("empty".to_string());
+
+        let bounds = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 400.0,
+            h: 100.0,
+        };
+        let nodes = [small, empty, big];
+        let rects = layout(&nodes, bounds);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0].0.path, "big");
+        assert_eq!(rects[0].1.w, 300.0);
+        assert_eq!(rects[1].1.x, 300.0);
+        assert_eq!(rects[1].1.w, 100.0);
+    }
+}
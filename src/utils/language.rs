@@ -0,0 +1,112 @@
+//! Language detection from file paths.
+//!
+//! Maps file extensions (and a few well-known file names) to the language
+//! identifiers commonly understood by Markdown renderers for fenced code blocks.
+
+use std::path::Path;
+
+/// Detect the language of a file from its name or extension.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// * `Option<&'static str>` - The language identifier, or `None` if it is unknown.
+pub fn detect_language(path: &str) -> Option<&'static str> {
+    let path = Path::new(path);
+    let file_name = path.file_name()?.to_str()?;
+
+    match file_name {
+        "Dockerfile" => return Some("dockerfile"),
+        "Makefile" | "makefile" | "GNUmakefile" => return Some("makefile"),
+        "justfile" | "Justfile" => return Some("just"),
+        "CMakeLists.txt" => return Some("cmake"),
+        _ => {}
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" => "scala",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "erl" | "hrl" => "erlang",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "clj" | "cljs" | "edn" => "clojure",
+        "zig" => "zig",
+        "nim" => "nim",
+        "r" => "r",
+        "pl" | "pm" => "perl",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "sass" => "sass",
+        "less" => "less",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" | "xsd" | "xsl" | "svg" => "xml",
+        "ini" | "cfg" => "ini",
+        "md" | "markdown" => "markdown",
+        "rst" => "rst",
+        "tex" => "latex",
+        "proto" => "protobuf",
+        "graphql" | "gql" => "graphql",
+        "tf" | "hcl" => "hcl",
+        "nix" => "nix",
+        "dockerfile" => "dockerfile",
+        "diff" | "patch" => "diff",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_by_extension() {
+        assert_eq!(detect_language("src/main.rs"), Some("rust"));
+        assert_eq!(detect_language("web/App.TSX"), Some("tsx"));
+        assert_eq!(detect_language("config.yml"), Some("yaml"));
+    }
+
+    #[test]
+    fn test_detect_language_by_file_name() {
+        assert_eq!(detect_language("docker/Dockerfile"), Some("dockerfile"));
+        assert_eq!(detect_language("Makefile"), Some("makefile"));
+    }
+
+    #[test]
+    fn test_detect_language_unknown() {
+        assert_eq!(detect_language("LICENSE"), None);
+        assert_eq!(detect_language("data.bin"), None);
+    }
+}
@@ -1,8 +1,17 @@
 //! Utility functions and helpers.
 //!
-//! Includes file finding, pattern filtering, and output formatting tools
+//! Includes file finding, pattern filtering, output formatting and archiving tools
 //! used across the application.
 
+pub mod archive;
+pub mod exclusion;
 pub mod filters;
 pub mod finder;
 pub mod format;
+pub mod html;
+pub mod language;
+pub mod priority;
+pub mod selection;
+pub mod split;
+pub mod template;
+pub mod unmerge;
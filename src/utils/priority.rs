@@ -0,0 +1,143 @@
+//! File priority rules.
+//!
+//! A context's `priorities` map glob patterns to weights. The first pattern
+//! matching a file's path below the scan root, in the order they are listed,
+//! gives its priority; files
+//! matching none get [`DEFAULT_PRIORITY`]. Higher priorities come first in the
+//! merged output and are the last to be dropped under a total budget.
+
+use crate::core::file::FileData;
+use crate::error::{Error, Result};
+use crate::utils::finder;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+
+/// The priority of files that match no pattern.
+pub const DEFAULT_PRIORITY: f64 = 1.0;
+
+/// Compiled priority rules.
+#[derive(Debug, Clone)]
+pub struct Priorities {
+    set: GlobSet,
+    weights: Vec<f64>,
+    root: PathBuf,
+}
+
+impl Priorities {
+    /// Compile `(pattern, weight)` rules, in order of precedence, matched
+    /// against paths relative to the scan `root`.
+    ///
+    /// Weights must be positive numbers.
+    pub fn new(rules: &[(String, f64)], root: &Path) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut weights = Vec::with_capacity(rules.len());
+        for (pattern, weight) in rules {
+            if !weight.is_finite() || *weight <= 0.0 {
+                return Err(Error::Config(format!(
+                    "Priority of '{}' must be a positive number, got {}",
+                    pattern, weight
+                )));
+            }
+            let glob = Glob::new(pattern)
+                .map_err(|e| Error::Filter(format!("Invalid glob pattern '{}': {}", pattern, e)))?;
+            builder.add(glob);
+            weights.push(*weight);
+        }
+        let set = builder
+            .build()
+            .map_err(|e| Error::Filter(format!("Failed to build glob set: {}", e)))?;
+        Ok(Self {
+            set,
+            weights,
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Whether no rules are configured, in which case every file has the
+    /// default priority.
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    /// The priority of the file at `path`.
+    pub fn weight(&self, path: &str) -> f64 {
+        self.set
+            .matches(finder::relative_path(path, &self.root))
+            .into_iter()
+            .min()
+            .map_or(DEFAULT_PRIORITY, |index| self.weights[index])
+    }
+
+    /// The priority of every file, in order.
+    pub fn weights(&self, files: &[FileData]) -> Vec<f64> {
+        files.iter().map(|f| self.weight(&f.path)).collect()
+    }
+
+    /// Order files by descending priority, keeping the existing order of
+    /// files with the same priority.
+    pub fn sort(&self, files: &mut [FileData]) {
+        if self.is_empty() {
+            return;
+        }
+        files.sort_by(|a, b| self.weight(&b.path).total_cmp(&self.weight(&a.path)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[(&str, f64)]) -> Priorities {
+        let rules: Vec<(String, f64)> = rules
+            .iter()
+            .map(|(pattern, weight)| (pattern.to_string(), *weight))
+            .collect();
+        Priorities::new(&rules, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let priorities = rules(&[("src/main.rs", 5.0), ("src/**", 2.0), ("**/tests/**", 0.2)]);
+        assert_eq!(priorities.weight("src/main.rs"), 5.0);
+        assert_eq!(priorities.weight("./src/lib.rs"), 2.0);
+        assert_eq!(priorities.weight("src/tests/it.rs"), 2.0);
+        assert_eq!(priorities.weight("crates/a/tests/it.rs"), 0.2);
+        assert_eq!(priorities.weight("Cargo.toml"), DEFAULT_PRIORITY);
+
+        assert!(Priorities::new(&[("src/**".to_string(), 0.0)], Path::new(".")).is_err());
+        assert!(Priorities::new(&[("src/[".to_string(), 1.0)], Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_rules_match_below_the_root() {
+        let rules = [
+            ("src/main.rs".to_string(), 5.0),
+            ("README.md".to_string(), 2.0),
+        ];
+        let priorities = Priorities::new(&rules, Path::new("../app")).unwrap();
+        assert_eq!(priorities.weight("../app/src/main.rs"), 5.0);
+        assert_eq!(priorities.weight("../app/README.md"), 2.0);
+        assert_eq!(priorities.weight("../app/docs/README.md"), DEFAULT_PRIORITY);
+
+        let priorities = Priorities::new(&rules, Path::new("/work/app/")).unwrap();
+        assert_eq!(priorities.weight("/work/app/src/main.rs"), 5.0);
+        assert_eq!(priorities.weight("/work/app/README.md"), 2.0);
+    }
+
+    #[test]
+    fn test_sort_is_stable() {
+        let priorities = rules(&[("README.md", 10.0), ("tests/**", 0.5)]);
+        let mut files: Vec<FileData> = ["tests/a.rs", "src/b.rs", "README.md", "src/a.rs"]
+            .iter()
+            .map(|path| FileData {
+                path: path.to_string(),
+                content: String::new(),
+                tokens: 0,
+                error: None,
+            })
+            .collect();
+        priorities.sort(&mut files);
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["README.md", "src/b.rs", "src/a.rs", "tests/a.rs"]);
+    }
+}
@@ -0,0 +1,352 @@
+//! Selecting files under a total token budget.
+//!
+//! Unlike the per-file budget filters, a total budget bounds the combined
+//! tokens of the selection. Files are picked by a [`Strategy`]; the files that
+//! do not make it are reported together with the reason they were dropped.
+//! Files with a higher priority are picked first, whatever the strategy.
+
+use crate::core::file::FileData;
+use crate::error::{Error, Result};
+use crate::utils::priority::DEFAULT_PRIORITY;
+
+/// The largest number of capacity units used by the knapsack table. Budgets
+/// above it are counted in coarser units, rounding file costs up, so the
+/// selection never exceeds the budget.
+const KNAPSACK_UNITS: usize = 4096;
+
+/// How files are picked when they do not all fit the total budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Take files in path order while they fit.
+    Path,
+    /// Take the smallest files first, keeping as many files as possible.
+    Smallest,
+    /// Take the largest files first.
+    Largest,
+    /// Maximize the total tokens kept, solved as a 0/1 knapsack for each
+    /// priority in turn, from the highest.
+    Knapsack,
+}
+
+impl Strategy {
+    /// Parse a `--strategy` value: `path`, `smallest`, `largest` or `knapsack`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "path" => Ok(Self::Path),
+            "smallest" => Ok(Self::Smallest),
+            "largest" => Ok(Self::Largest),
+            "knapsack" => Ok(Self::Knapsack),
+            _ => Err(Error::Config(format!(
+                "Unknown selection strategy: {}",
+                name
+            ))),
+        }
+    }
+
+    /// The name of the strategy, as accepted by [`Strategy::from_name`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::Smallest => "smallest",
+            Self::Largest => "largest",
+            Self::Knapsack => "knapsack",
+        }
+    }
+}
+
+/// A file left out of the selection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropped {
+    /// The path of the file.
+    pub path: String,
+    /// The cost of the file in tokens.
+    pub tokens: usize,
+    /// The priority of the file.
+    pub priority: f64,
+    /// Why the file was left out.
+    pub reason: String,
+}
+
+/// The outcome of selecting files under a total budget.
+#[derive(Debug)]
+pub struct Selection {
+    /// The selected files, in their original order.
+    pub kept: Vec<FileData>,
+    /// The files left out, in their original order.
+    pub dropped: Vec<Dropped>,
+    /// The combined cost of the selected files.
+    pub tokens: usize,
+}
+
+/// Pick a subset of `files` whose combined cost fits `budget`.
+///
+/// # Arguments
+///
+/// * `files` - The candidate files.
+/// * `costs` - The cost of each file, usually its token count.
+/// * `priorities` - The priority of each file; within the strategy's order,
+///   higher priorities are taken first.
+/// * `budget` - The total token budget.
+/// * `strategy` - How files are picked when they do not all fit.
+///
+/// # Returns
+///
+/// * `Selection` - The kept files and the dropped ones with a reason.
+pub fn select_files(
+    files: Vec<FileData>,
+    costs: &[usize],
+    priorities: &[f64],
+    budget: usize,
+    strategy: Strategy,
+) -> Selection {
+    let total: usize = costs.iter().sum();
+    let mut reasons: Vec<Option<String>> = vec![None; files.len()];
+
+    if total > budget {
+        let candidates: Vec<usize> = (0..files.len())
+            .filter(|&i| {
+                if costs[i] > budget {
+                    reasons[i] = Some(format!("larger than the total budget of {}", budget));
+                }
+                costs[i] <= budget
+            })
+            .collect();
+
+        match strategy {
+            Strategy::Knapsack => {
+                // Like the greedy strategies, a file never makes room for one
+                // with a lower priority: each priority fills what the higher
+                // ones left of the budget, keeping as many tokens as it can.
+                let mut order = candidates;
+                order.sort_by(|&a, &b| priorities[b].total_cmp(&priorities[a]));
+                let mut remaining = budget;
+                for level in order.chunk_by(|&a, &b| priorities[a] == priorities[b]) {
+                    let chosen = knapsack(level, costs, remaining);
+                    for (&i, keep) in level.iter().zip(chosen) {
+                        if keep {
+                            remaining -= costs[i];
+                        } else {
+                            reasons[i] = Some("left out by the knapsack selection".to_string());
+                        }
+                    }
+                }
+            }
+            _ => {
+                let mut order = candidates;
+                match strategy {
+                    Strategy::Smallest => order.sort_by_key(|&i| (costs[i], i)),
+                    Strategy::Largest => order.sort_by_key(|&i| (std::cmp::Reverse(costs[i]), i)),
+                    _ => order.sort_by(|&a, &b| files[a].path.cmp(&files[b].path)),
+                }
+                order.sort_by(|&a, &b| priorities[b].total_cmp(&priorities[a]));
+                let mut remaining = budget;
+                for i in order {
+                    if costs[i] <= remaining {
+                        remaining -= costs[i];
+                    } else {
+                        reasons[i] = Some(format!(
+                            "does not fit the remaining {} tokens ({} order)",
+                            remaining,
+                            strategy.as_str()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut selection = Selection {
+        kept: Vec::new(),
+        dropped: Vec::new(),
+        tokens: 0,
+    };
+    for (((file, reason), &cost), &priority) in
+        files.into_iter().zip(reasons).zip(costs).zip(priorities)
+    {
+        match reason {
+            Some(reason) => selection.dropped.push(Dropped {
+                path: file.path,
+                tokens: cost,
+                priority,
+                reason,
+            }),
+            None => {
+                selection.tokens += cost;
+                selection.kept.push(file);
+            }
+        }
+    }
+    selection
+}
+
+/// Solve the 0/1 knapsack over the `candidates` (indices into `costs`),
+/// maximizing the kept tokens. Returns whether each candidate is kept.
+fn knapsack(candidates: &[usize], costs: &[usize], budget: usize) -> Vec<bool> {
+    let unit = budget.div_ceil(KNAPSACK_UNITS).max(1);
+    let capacity = budget / unit;
+    let weights: Vec<usize> = candidates
+        .iter()
+        .map(|&i| costs[i].div_ceil(unit))
+        .collect();
+
+    // best[w] is the most tokens that fit in w units; taken records,
+    // per candidate, at which capacities it improved on the previous candidates.
+    let mut best = vec![0usize; capacity + 1];
+    let words = capacity / 64 + 1;
+    let mut taken = vec![0u64; candidates.len() * words];
+    for (n, &i) in candidates.iter().enumerate() {
+        let weight = weights[n];
+        for w in (weight..=capacity).rev() {
+            let value = best[w - weight] + costs[i];
+            if value > best[w] {
+                best[w] = value;
+                taken[n * words + w / 64] |= 1 << (w % 64);
+            }
+        }
+    }
+
+    let mut chosen = vec![false; candidates.len()];
+    let mut w = capacity;
+    for n in (0..candidates.len()).rev() {
+        if taken[n * words + w / 64] & (1 << (w % 64)) != 0 {
+            chosen[n] = true;
+            w -= weights[n];
+        }
+    }
+    chosen
+}
+
+impl Selection {
+    /// Describe the dropped files, one per line, for printing to stderr.
+    pub fn format_report(&self, budget: usize, strategy: Strategy) -> String {
+        let mut result = format!(
+            "Total budget {} ({}): kept {} files ({} tokens), dropped {}\n",
+            budget,
+            strategy.as_str(),
+            self.kept.len(),
+            self.tokens,
+            self.dropped.len()
+        );
+        for dropped in &self.dropped {
+            let priority = if dropped.priority == DEFAULT_PRIORITY {
+                String::new()
+            } else {
+                format!(", priority {}", dropped.priority)
+            };
+            result.push_str(&format!(
+                "  dropped {} ({} tokens{}): {}\n",
+                dropped.path, dropped.tokens, priority, dropped.reason
+            ));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(sizes: &[usize]) -> (Vec<FileData>, Vec<usize>) {
+        let files = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| FileData {
+                path: format!("f{}.txt", i),
+                content: String::new(),
+                tokens: size,
+                error: None,
+            })
+            .collect();
+        (files, sizes.to_vec())
+    }
+
+    fn kept(selection: &Selection) -> Vec<&str> {
+        selection.kept.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_everything_fits() {
+        let (files, costs) = files(&[10, 20, 30]);
+        let selection = select_files(files, &costs, &[1.0; 3], 60, Strategy::Largest);
+        assert_eq!(selection.kept.len(), 3);
+        assert!(selection.dropped.is_empty());
+    }
+
+    #[test]
+    fn test_greedy_strategies() {
+        let (all, costs) = files(&[50, 10, 40, 30, 200]);
+        let ones = vec![DEFAULT_PRIORITY; costs.len()];
+
+        let selection = select_files(all.clone(), &costs, &ones, 90, Strategy::Path);
+        assert_eq!(kept(&selection), ["f0.txt", "f1.txt", "f3.txt"]);
+        assert!(selection.dropped[1]
+            .reason
+            .contains("larger than the total budget"));
+
+        let selection = select_files(all.clone(), &costs, &ones, 90, Strategy::Smallest);
+        assert_eq!(kept(&selection), ["f1.txt", "f2.txt", "f3.txt"]);
+        assert_eq!(selection.tokens, 80);
+
+        let selection = select_files(all, &costs, &ones, 90, Strategy::Largest);
+        assert_eq!(kept(&selection), ["f0.txt", "f2.txt"]);
+        assert!(selection
+            .format_report(90, Strategy::Largest)
+            .contains("dropped f1.txt (10 tokens): does not fit the remaining 0 tokens"));
+    }
+
+    #[test]
+    fn test_knapsack_fills_the_budget() {
+        let (all, costs) = files(&[60, 50, 45, 30]);
+        // Largest-first keeps 60 + 30 = 90; the knapsack finds 50 + 45 = 95.
+        let selection = select_files(all, &costs, &[1.0; 4], 95, Strategy::Knapsack);
+        assert_eq!(kept(&selection), ["f1.txt", "f2.txt"]);
+        assert_eq!(selection.tokens, 95);
+
+        // Coarse units on large budgets never overshoot.
+        let sizes: Vec<usize> = (1..=40).map(|i| i * 997).collect();
+        let (all, costs) = files(&sizes);
+        let selection = select_files(all, &costs, &[1.0; 40], 300_000, Strategy::Knapsack);
+        assert!(selection.tokens <= 300_000);
+        assert!(selection.tokens > 290_000);
+    }
+
+    #[test]
+    fn test_priorities_drive_the_selection() {
+        let (all, costs) = files(&[40, 40, 40]);
+        // f0 is a test file, f2 the README.
+        let priorities = [0.1, 1.0, 10.0];
+
+        let selection = select_files(all.clone(), &costs, &priorities, 80, Strategy::Path);
+        assert_eq!(kept(&selection), ["f1.txt", "f2.txt"]);
+        assert!(selection
+            .format_report(80, Strategy::Path)
+            .contains("dropped f0.txt (40 tokens, priority 0.1)"));
+
+        let selection = select_files(all, &costs, &priorities, 80, Strategy::Knapsack);
+        assert_eq!(kept(&selection), ["f1.txt", "f2.txt"]);
+
+        // A high priority outweighs a larger file in the knapsack.
+        let (all, costs) = files(&[60, 30, 20]);
+        let selection = select_files(all, &costs, &[1.0, 3.0, 1.0], 60, Strategy::Knapsack);
+        assert_eq!(kept(&selection), ["f1.txt", "f2.txt"]);
+    }
+
+    #[test]
+    fn test_knapsack_orders_priorities_like_greedy() {
+        // Weighting tokens by priority would keep f0 (100 x 1.0 > 10 x 2.0),
+        // dropping the higher priority f1 as the greedy strategies never do.
+        let (all, costs) = files(&[100, 10]);
+        let priorities = [1.0, 2.0];
+        for strategy in [Strategy::Path, Strategy::Largest, Strategy::Knapsack] {
+            let selection = select_files(all.clone(), &costs, &priorities, 100, strategy);
+            assert_eq!(kept(&selection), ["f1.txt"], "{}", strategy.as_str());
+        }
+
+        // Within a priority, the knapsack still packs the remaining budget:
+        // f0 takes 40, leaving 60 that 35 + 25 fill better than 50 alone.
+        let (all, costs) = files(&[40, 50, 35, 25]);
+        let selection = select_files(all, &costs, &[5.0, 1.0, 1.0, 1.0], 100, Strategy::Knapsack);
+        assert_eq!(kept(&selection), ["f0.txt", "f2.txt", "f3.txt"]);
+        assert_eq!(selection.tokens, 100);
+    }
+}
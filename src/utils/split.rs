@@ -0,0 +1,518 @@
+//! Splitting merged output into token-bounded parts.
+//!
+//! Packs files in order into numbered parts (`out.part-001.md`, ...) so that
+//! each rendered part stays under a token limit, and writes an index file
+//! listing which files landed in which part.
+
+use super::format::{self, OutputOptions};
+use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
+use crate::core::tokens::count_tokens;
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A group of files rendered together into one output part.
+#[derive(Debug, Default)]
+pub struct Part {
+    /// The files (or file chunks) in this part.
+    pub files: Vec<FileData>,
+    /// The estimated rendered size of this part in tokens.
+    pub tokens: usize,
+}
+
+#[derive(Serialize)]
+struct IndexEntry {
+    part: String,
+    tokens: usize,
+    files: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Index {
+    tokenizer: String,
+    estimated: bool,
+    max_tokens: usize,
+    parts: Vec<IndexEntry>,
+}
+
+/// Pack files into parts whose rendered size stays under `max_tokens`.
+///
+/// Files keep their order and are never cut unless `allow_file_split` is set,
+/// in which case oversized files are cut on line boundaries and each piece is
+/// marked as continuing in, or continued from, a neighbouring part. Without it,
+/// a file larger than the limit gets a part of its own and a warning on stderr.
+///
+/// # Arguments
+///
+/// * `files` - The files to pack.
+/// * `format_type` - The output format the parts will be rendered in.
+/// * `options` - The preamble sections, which are rendered into the first part only.
+/// * `max_tokens` - The token limit for each part.
+/// * `allow_file_split` - Whether files larger than the limit may be cut.
+pub fn split_files(
+    files: &[FileData],
+    format_type: &str,
+    options: &OutputOptions,
+    max_tokens: usize,
+    allow_file_split: bool,
+) -> Result<Vec<Part>> {
+    let tokenizer = options.tokenizer;
+    let overhead = render_cost(&[], format_type, tokenizer)?;
+    let mut parts = vec![Part {
+        files: Vec::new(),
+        tokens: format::shared_cost(files, format_type, options)?,
+    }];
+
+    for file in files {
+        let cost = render_cost(std::slice::from_ref(file), format_type, tokenizer)?
+            .saturating_sub(overhead);
+        let current = parts.last_mut().unwrap();
+
+        if current.tokens + cost <= max_tokens {
+            current.tokens += cost;
+            current.files.push(file.clone());
+            continue;
+        }
+
+        // The first part is never left with the preamble alone, as it is
+        // always rendered into the first part written.
+        let occupied = !current.files.is_empty();
+        if (occupied && overhead + cost <= max_tokens) || !allow_file_split {
+            if occupied {
+                parts.push(Part {
+                    files: Vec::new(),
+                    tokens: overhead,
+                });
+            }
+            let current = parts.last_mut().unwrap();
+            current.tokens += cost;
+            current.files.push(file.clone());
+            if current.tokens > max_tokens {
+                eprintln!(
+                    "Warning: {} ({} tokens) exceeds the {} token part limit",
+                    file.path, cost, max_tokens
+                );
+            }
+            continue;
+        }
+
+        // A file is only cut at the start of a part, which is empty apart
+        // from the preamble when it is the first one.
+        if occupied {
+            parts.push(Part {
+                files: Vec::new(),
+                tokens: overhead,
+            });
+        }
+        let first_used = parts.last().map_or(overhead, |part| part.tokens);
+        let chunks = split_file(
+            file,
+            format_type,
+            tokenizer,
+            max_tokens,
+            overhead,
+            first_used,
+            parts.len(),
+        )?;
+        let chunk_count = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let current = parts.last_mut().unwrap();
+            current.tokens += render_cost(std::slice::from_ref(&chunk), format_type, tokenizer)?
+                .saturating_sub(overhead);
+            current.files.push(chunk);
+            if i + 1 < chunk_count {
+                parts.push(Part {
+                    files: Vec::new(),
+                    tokens: overhead,
+                });
+            }
+        }
+    }
+
+    parts.retain(|p| !p.files.is_empty());
+    Ok(parts)
+}
+
+/// Cut a file into line-aligned chunks for consecutive parts.
+///
+/// The first chunk goes into part `first_part`, which already holds
+/// `first_used` tokens of framing and preamble; every later chunk starts an
+/// empty part. Chunks only end on line boundaries, so a single line too long
+/// for a part is an error.
+fn split_file(
+    file: &FileData,
+    format_type: &str,
+    tokenizer: &dyn Tokenizer,
+    max_tokens: usize,
+    overhead: usize,
+    first_used: usize,
+    first_part: usize,
+) -> Result<Vec<FileData>> {
+    let wrapper = render_cost(
+        &[FileData::new(file.path.clone(), "x", tokenizer)],
+        format_type,
+        tokenizer,
+    )?
+    .saturating_sub(overhead);
+    let marker = count_tokens(
+        &continuation_marker(&file.path, "continues in", first_part),
+        tokenizer,
+    );
+    let limit =
+        |chunk: usize| max_tokens.saturating_sub(if chunk == 0 { first_used } else { overhead });
+    let room = |chunk: usize| limit(chunk).saturating_sub(wrapper + 2 * marker);
+    if room(0) == 0 || room(1) == 0 {
+        return Err(anyhow::anyhow!(
+            "Token limit {} is too small to hold any part of {}",
+            max_tokens,
+            file.path
+        ));
+    }
+
+    let mut bodies: Vec<Vec<&str>> = Vec::new();
+    let mut body: Vec<&str> = Vec::new();
+    let mut body_tokens = 0;
+    for (index, piece) in file.content.split_inclusive('\n').enumerate() {
+        let piece_tokens = count_tokens(piece, tokenizer);
+        if body_tokens + piece_tokens > room(bodies.len()) && !body.is_empty() {
+            bodies.push(std::mem::take(&mut body));
+            body_tokens = 0;
+        }
+        if body_tokens + piece_tokens > room(bodies.len()) {
+            return Err(anyhow::anyhow!(
+                "Line {} of {} ({} tokens) does not fit in a {} token part",
+                index + 1,
+                file.path,
+                piece_tokens,
+                max_tokens
+            ));
+        }
+        body.push(piece);
+        body_tokens += piece_tokens;
+    }
+    if !body.is_empty() {
+        bodies.push(body);
+    }
+
+    // Per-piece counts only approximate the rendered chunks, so move pieces
+    // on to the next chunk until every chunk fits its part.
+    loop {
+        let chunks = build_chunks(file, &bodies, first_part, tokenizer);
+        let mut overflowing = None;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let cost = render_cost(std::slice::from_ref(chunk), format_type, tokenizer)?
+                .saturating_sub(overhead);
+            if cost > limit(i) && bodies[i].len() > 1 {
+                overflowing = Some(i);
+                break;
+            }
+        }
+        let Some(i) = overflowing else {
+            return Ok(chunks);
+        };
+        let piece = bodies[i].pop().unwrap_or_default();
+        if i + 1 == bodies.len() {
+            bodies.push(Vec::new());
+        }
+        bodies[i + 1].insert(0, piece);
+    }
+}
+
+/// Join the pieces of each chunk, adding the continuation markers between them.
+fn build_chunks(
+    file: &FileData,
+    bodies: &[Vec<&str>],
+    first_part: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<FileData> {
+    let count = bodies.len();
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(i, body)| {
+            let mut content = String::new();
+            if i > 0 {
+                content.push_str(&continuation_marker(
+                    &file.path,
+                    "continued from",
+                    first_part + i - 1,
+                ));
+            }
+            content.push_str(&body.concat());
+            if i + 1 < count {
+                if !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(&continuation_marker(
+                    &file.path,
+                    "continues in",
+                    first_part + i + 1,
+                ));
+            }
+            FileData::new(file.path.clone(), content, tokenizer)
+        })
+        .collect()
+}
+
+fn continuation_marker(path: &str, relation: &str, part: usize) -> String {
+    format!("[... {} {} part {:03} ...]\n", path, relation, part)
+}
+
+fn render_cost(files: &[FileData], format_type: &str, tokenizer: &dyn Tokenizer) -> Result<usize> {
+    let options = OutputOptions {
+        tokenizer,
+        ..OutputOptions::default()
+    };
+    render_cost_with(files, format_type, &options)
+}
+
+fn render_cost_with(
+    files: &[FileData],
+    format_type: &str,
+    options: &OutputOptions,
+) -> Result<usize> {
+    Ok(count_tokens(
+        &format::render(files, format_type, options)?,
+        options.tokenizer,
+    ))
+}
+
+/// Build the path of a numbered part next to `output`, e.g. `out.part-001.md`.
+pub fn part_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match output.extension() {
+        Some(ext) => format!("{}.part-{:03}.{}", stem, number, ext.to_string_lossy()),
+        None => format!("{}.part-{:03}", stem, number),
+    };
+    output.with_file_name(name)
+}
+
+/// Render every part into its own file and write the `<stem>.index.json` index.
+///
+/// The preamble sections from `options` are only rendered into the first part.
+/// The index and the returned list carry the exact token count of each
+/// rendered part.
+pub fn write_parts(
+    parts: &[Part],
+    format_type: &str,
+    options: &OutputOptions,
+    output: &Path,
+    max_tokens: usize,
+) -> Result<Vec<usize>> {
+    let mut index = Index {
+        tokenizer: options.tokenizer.name().to_string(),
+        estimated: options.tokenizer.is_estimate(),
+        max_tokens,
+        parts: Vec::new(),
+    };
+
+    for (i, part) in parts.iter().enumerate() {
+        let path = part_path(output, i + 1);
+        let part_options = if i == 0 {
+            *options
+        } else {
+            OutputOptions {
+                with_meta: options.with_meta,
+                tokenizer: options.tokenizer,
+                ..OutputOptions::default()
+            }
+        };
+        let content = format::render(&part.files, format_type, &part_options)?;
+        let tokens = count_tokens(&content, options.tokenizer);
+        std::fs::write(&path, content)?;
+
+        let mut files: Vec<String> = part.files.iter().map(|f| f.path.clone()).collect();
+        files.dedup();
+        index.parts.push(IndexEntry {
+            part: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            tokens,
+            files,
+        });
+    }
+
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let index_path = output.with_file_name(format!("{}.index.json", stem));
+    std::fs::write(index_path, serde_json::to_string_pretty(&index)?)?;
+
+    Ok(index.parts.iter().map(|p| p.tokens).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count)
+            .map(|i| format!("let value_{} = {};\n", i, i))
+            .collect()
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("out/merged.md"), 1),
+            PathBuf::from("out/merged.part-001.md")
+        );
+        assert_eq!(
+            part_path(Path::new("merged"), 12),
+            PathBuf::from("merged.part-012")
+        );
+    }
+
+    #[test]
+    fn test_split_keeps_files_whole() {
+        let files: Vec<FileData> = (0..6)
+            .map(|i| {
+                FileData::new(
+                    format!("src/file{}.rs", i),
+                    numbered_lines(20),
+                    &Encoding::default(),
+                )
+            })
+            .collect();
+
+        let parts = split_files(&files, "markdown", &OutputOptions::default(), 400, false).unwrap();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.tokens <= 400));
+        let total: usize = parts.iter().map(|p| p.files.len()).sum();
+        assert_eq!(total, files.len());
+
+        for part in &parts {
+            let rendered = format::render(&part.files, "markdown", &OutputOptions::default());
+            assert!(count_tokens(&rendered.unwrap(), &Encoding::default()) <= 400);
+        }
+    }
+
+    #[test]
+    fn test_split_oversized_file() {
+        let files = vec![FileData::new(
+            "src/big.rs",
+            numbered_lines(200),
+            &Encoding::default(),
+        )];
+
+        let whole = split_files(&files, "text", &OutputOptions::default(), 300, false).unwrap();
+        assert_eq!(whole.len(), 1);
+        assert!(whole[0].tokens > 300);
+
+        let parts = split_files(&files, "text", &OutputOptions::default(), 300, true).unwrap();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.tokens <= 300));
+        assert!(parts[0].files[0]
+            .content
+            .ends_with("[... src/big.rs continues in part 002 ...]\n"));
+        assert!(parts[1].files[0]
+            .content
+            .starts_with("[... src/big.rs continued from part 001 ...]\n"));
+
+        let rejoined: String = parts
+            .iter()
+            .flat_map(|p| p.files.iter())
+            .flat_map(|f| f.content.lines())
+            .filter(|line| !line.starts_with("[... "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(rejoined, numbered_lines(200));
+    }
+
+    #[test]
+    fn test_split_refuses_to_cut_inside_a_line() {
+        let encoding = Encoding::default();
+        let content = format!(
+            "{}let data = \"{}\";\n",
+            numbered_lines(3),
+            "abc ".repeat(400)
+        );
+        let files = vec![FileData::new("src/long.rs", content, &encoding)];
+
+        let err = split_files(&files, "text", &OutputOptions::default(), 300, true).unwrap_err();
+        assert!(err.to_string().starts_with("Line 4 of src/long.rs ("));
+    }
+
+    #[test]
+    fn test_written_parts_stay_under_the_limit_with_preamble() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let output = temp_dir.path().join("out.md");
+        let encoding = Encoding::default();
+        let long_line = format!("let data = \"{}\";\n", "abc ".repeat(150));
+        let files = vec![
+            FileData::new("src/big.rs", numbered_lines(150), &encoding),
+            FileData::new("src/long.rs", long_line.clone(), &encoding),
+            FileData::new("src/small.rs", numbered_lines(5), &encoding),
+        ];
+        let options = OutputOptions {
+            with_tree: true,
+            with_stats: true,
+            with_meta: false,
+            tokenizer: &encoding,
+            errors: &[],
+        };
+
+        for format_type in ["text", "markdown"] {
+            let parts = split_files(&files, format_type, &options, 300, true).unwrap();
+            let tokens = write_parts(&parts, format_type, &options, &output, 300).unwrap();
+            assert!(tokens.len() > 2);
+            for number in 1..=tokens.len() {
+                let written = std::fs::read_to_string(part_path(&output, number)).unwrap();
+                assert!(
+                    count_tokens(&written, &encoding) <= 300,
+                    "part {} of the {} output is over the limit",
+                    number,
+                    format_type
+                );
+            }
+
+            let long: String = parts
+                .iter()
+                .flat_map(|p| p.files.iter())
+                .filter(|f| f.path == "src/long.rs")
+                .flat_map(|f| f.content.lines())
+                .filter(|line| !line.starts_with("[... "))
+                .collect();
+            assert_eq!(long, long_line.trim_end());
+        }
+    }
+
+    #[test]
+    fn test_write_parts_and_index() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let output = temp_dir.path().join("out.md");
+        let files: Vec<FileData> = (0..4)
+            .map(|i| {
+                FileData::new(
+                    format!("f{}.rs", i),
+                    numbered_lines(30),
+                    &Encoding::default(),
+                )
+            })
+            .collect();
+
+        let parts = split_files(&files, "markdown", &OutputOptions::default(), 500, false).unwrap();
+        write_parts(&parts, "markdown", &OutputOptions::default(), &output, 500).unwrap();
+
+        assert!(temp_dir.path().join("out.part-001.md").exists());
+        let index: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(temp_dir.path().join("out.index.json")).unwrap(),
+        )
+        .unwrap();
+        let indexed: usize = index["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["files"].as_array().unwrap().len())
+            .sum();
+        assert_eq!(index["parts"].as_array().unwrap().len(), parts.len());
+        assert_eq!(indexed, 4);
+    }
+}
@@ -0,0 +1,193 @@
+//! User-defined output templates for the merge command.
+//!
+//! A template file is a YAML document with three sections, each rendered
+//! with the [Tera](https://keats.github.io/tera/) template engine:
+//!
+//! ```yaml
+//! header: "# {{ total_files }} files, {{ total_tokens }} tokens\n"
+//! file: "## {{ relative_path }}\n{{ content }}\n"
+//! footer: "-- end --\n"
+//! ```
+//!
+//! `header` and `footer` receive the selection totals, and `file` is
+//! rendered once per file with that file's details.
+
+use super::language::detect_language;
+use crate::core::file::FileData;
+use crate::core::tokenizer::Tokenizer;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tera::{Context, Tera};
+
+const HEADER: &str = "header";
+const FILE: &str = "file";
+const FOOTER: &str = "footer";
+
+#[derive(Debug, Deserialize)]
+struct TemplateFile {
+    #[serde(default)]
+    header: String,
+    file: String,
+    #[serde(default)]
+    footer: String,
+}
+
+/// Per-file values available to the `file` section.
+#[derive(Serialize)]
+struct FileContext<'a> {
+    index: usize,
+    path: &'a str,
+    relative_path: String,
+    extension: &'a str,
+    language: &'a str,
+    tokens: usize,
+    content: &'a str,
+    line_count: usize,
+    error: Option<&'a str>,
+}
+
+/// Totals available to the `header` and `footer` sections.
+#[derive(Serialize)]
+struct TotalsContext<'a> {
+    total_files: usize,
+    total_tokens: usize,
+    total_lines: usize,
+    tokenizer: &'a str,
+    estimated: bool,
+}
+
+/// A parsed output template, ready to render a set of files.
+pub struct Template {
+    tera: Tera,
+}
+
+impl Template {
+    /// Load a template from a YAML file on disk.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            Error::Template(format!("Failed to read template {}: {}", path.display(), e))
+        })?;
+        Self::parse(&source)
+    }
+
+    /// Parse a template from its YAML source.
+    pub fn parse(source: &str) -> Result<Self> {
+        let file: TemplateFile = serde_yaml::from_str(source)?;
+
+        let mut tera = Tera::default();
+        tera.autoescape_on(vec![]);
+        tera.add_raw_templates(vec![
+            (HEADER, file.header),
+            (FILE, file.file),
+            (FOOTER, file.footer),
+        ])
+        .map_err(template_error)?;
+
+        Ok(Self { tera })
+    }
+
+    /// Render the header, each file and the footer into a single string.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - The files to render.
+    /// * `root` - The merge root, used to compute each file's relative path.
+    /// * `tokenizer` - The tokenizer the token counts were computed with.
+    pub fn render(
+        &self,
+        files: &[FileData],
+        root: &Path,
+        tokenizer: &dyn Tokenizer,
+    ) -> Result<String> {
+        let totals = TotalsContext {
+            total_files: files.len(),
+            total_tokens: files.iter().map(|f| f.tokens).sum(),
+            total_lines: files.iter().map(|f| f.content.lines().count()).sum(),
+            tokenizer: tokenizer.name(),
+            estimated: tokenizer.is_estimate(),
+        };
+        let totals = Context::from_serialize(&totals).map_err(template_error)?;
+
+        let mut output = self.tera.render(HEADER, &totals).map_err(template_error)?;
+
+        for (i, file) in files.iter().enumerate() {
+            let path = Path::new(&file.path);
+            let file_context = FileContext {
+                index: i + 1,
+                path: &file.path,
+                relative_path: path
+                    .strip_prefix(root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .to_string(),
+                extension: path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+                language: detect_language(&file.path).unwrap_or(""),
+                tokens: file.tokens,
+                content: &file.content,
+                line_count: file.content.lines().count(),
+                error: file.error.as_deref(),
+            };
+            let context = Context::from_serialize(&file_context).map_err(template_error)?;
+            output.push_str(&self.tera.render(FILE, &context).map_err(template_error)?);
+        }
+
+        output.push_str(&self.tera.render(FOOTER, &totals).map_err(template_error)?);
+        Ok(output)
+    }
+}
+
+fn template_error(e: tera::Error) -> Error {
+    // Tera keeps the useful detail (e.g. the parse location) in the error source.
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(inner) = source {
+        message.push_str(&format!(": {}", inner));
+        source = inner.source();
+    }
+    Error::Template(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::tokenizer::Encoding;
+
+    #[test]
+    fn test_render_sections() {
+        let template = Template::parse(
+            r#"
+header: "{{ total_files }} files, {{ total_tokens }} tokens\n"
+file: "[{{ index }}] {{ relative_path }} ({{ language }}, {{ extension }}, {{ line_count }} lines)\n{{ content }}\n"
+footer: "end: {{ total_lines }} lines\n"
+"#,
+        )
+        .unwrap();
+
+        let files = vec![
+            FileData::new(
+                "root/src/main.rs",
+                "fn main() {\n    <tag>\n}",
+                &Encoding::default(),
+            ),
+            FileData::new("root/notes.txt", "hello", &Encoding::default()),
+        ];
+        let tokens: usize = files.iter().map(|f| f.tokens).sum();
+
+        let rendered = template
+            .render(&files, Path::new("root"), &Encoding::default())
+            .unwrap();
+        assert!(rendered.starts_with(&format!("2 files, {} tokens\n", tokens)));
+        assert!(
+            rendered.contains("[1] src/main.rs (rust, rs, 3 lines)\nfn main() {\n    <tag>\n}\n")
+        );
+        assert!(rendered.contains("[2] notes.txt (, txt, 1 lines)\nhello\n"));
+        assert!(rendered.ends_with("end: 4 lines\n"));
+    }
+
+    #[test]
+    fn test_invalid_template() {
+        assert!(Template::parse("file: \"{{ unclosed\"").is_err());
+        assert!(Template::parse("header: only a header").is_err());
+    }
+}
@@ -0,0 +1,707 @@
+//! Reconstruction of files from merged output.
+//!
+//! Parses the text, Markdown, XML and JSON documents produced by `merge`
+//! back into individual files, and writes them below a target directory
+//! (or shows what would change). Paths that would escape the target
+//! directory are always rejected.
+//!
+//! XML and JSON round-trip exactly. Text and Markdown end every file with a
+//! newline, and in text output a content line starting with `File: ` reads
+//! as the header of a new file.
+
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use similar::TextDiff;
+use std::path::{Component, Path, PathBuf};
+
+/// A file recovered from merged output.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MergedFile {
+    /// The path recorded in the merged output.
+    pub path: String,
+    /// The file content.
+    #[serde(default)]
+    pub content: String,
+    /// The read error recorded for the file, if any.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Guess the format of merged output from its first characters.
+pub fn detect_format(input: &str) -> &'static str {
+    let start = input.trim_start();
+    if start.starts_with("<documents>") {
+        "xml"
+    } else if start.starts_with('[') || start.starts_with('{') {
+        "json"
+    } else if start.starts_with("=== ") {
+        "text"
+    } else {
+        "markdown"
+    }
+}
+
+/// Parse merged output in the given format ("auto" detects it).
+///
+/// Entries that only carry a read error have no content to restore and are skipped.
+pub fn parse(input: &str, format: &str) -> Result<Vec<MergedFile>> {
+    let format = if format == "auto" {
+        detect_format(input)
+    } else {
+        format
+    };
+
+    let files = match format {
+        "text" => parse_text(input)?,
+        "markdown" => parse_markdown(input)?,
+        "xml" => parse_xml(input)?,
+        "json" => parse_json(input)?,
+        _ => return Err(Error::Unmerge(format!("Unsupported format: {}", format))),
+    };
+
+    Ok(files.into_iter().filter(|f| f.error.is_none()).collect())
+}
+
+/// Split text output into files: each one runs from its `File:` header to
+/// the next header or the end of the input, so edited content may gain or
+/// lose lines freely.
+fn parse_text(input: &str) -> Result<Vec<MergedFile>> {
+    let mut lines = input.split_inclusive('\n');
+
+    // Skip any preamble sections ahead of the merged files.
+    if input.lines().any(|line| line == "=== Result ===") {
+        for line in lines.by_ref() {
+            if line.trim_end_matches('\n') == "=== Result ===" {
+                break;
+            }
+        }
+    }
+
+    let mut files: Vec<MergedFile> = Vec::new();
+    for line in lines {
+        if let Some(path) = parse_text_header(line.trim_end_matches('\n')) {
+            files.push(MergedFile {
+                path,
+                content: String::new(),
+                error: None,
+            });
+        } else if let Some(file) = files.last_mut() {
+            file.content.push_str(line);
+        } else if !line.trim().is_empty() {
+            return Err(Error::Unmerge(format!(
+                "Expected a 'File: <path>' header, found: {}",
+                line.trim_end()
+            )));
+        }
+    }
+
+    // The blank line printed after output sent to stdout is not content.
+    if let Some(file) = files.last_mut() {
+        if file.content.ends_with("\n\n") {
+            file.content.pop();
+        }
+    }
+    Ok(files)
+}
+
+/// Parse a `File: <path>` header into the path. A `(<n> lines)` suffix, as
+/// written by some versions, is only a hint and is dropped.
+fn parse_text_header(header: &str) -> Option<String> {
+    let path = header.strip_prefix("File: ")?;
+    let hint = path
+        .strip_suffix(" lines)")
+        .or_else(|| path.strip_suffix(" line)"))
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .filter(|(_, count)| !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit()));
+    Some(hint.map_or(path, |(path, _)| path).to_string())
+}
+
+/// The headings of the tree and stats sections `merge` may put ahead of the
+/// files in Markdown output, each followed by a `text` code block.
+const MARKDOWN_PREAMBLE: &[&str] = &["Tree", "Tree (estimated tokens)", "Token Statistics"];
+
+fn parse_markdown(input: &str) -> Result<Vec<MergedFile>> {
+    let lines: Vec<&str> = input.split_inclusive('\n').collect();
+    let mut files = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i].trim_end_matches('\n');
+
+        if let Some(fence) = opening_fence(line) {
+            // A fenced block outside of a file section, e.g. the tree preamble.
+            i = closing_fence(&lines, i + 1, fence).map_or(lines.len(), |end| end + 1);
+            continue;
+        }
+
+        let Some(path) = line.strip_prefix("## ") else {
+            i += 1;
+            continue;
+        };
+
+        let mut j = i + 1;
+        while j < lines.len() && lines[j].trim().is_empty() {
+            j += 1;
+        }
+        let Some(fence) = lines
+            .get(j)
+            .and_then(|l| opening_fence(l.trim_end_matches('\n')))
+        else {
+            i += 1;
+            continue;
+        };
+
+        let end = closing_fence(&lines, j + 1, fence)
+            .ok_or_else(|| Error::Unmerge(format!("Unterminated code block for {}", path)))?;
+        let info = lines[j].trim_end_matches('\n')[fence.len()..].trim();
+        if files.is_empty() && info == "text" && MARKDOWN_PREAMBLE.contains(&path) {
+            i = end + 1;
+            continue;
+        }
+        files.push(MergedFile {
+            path: path.to_string(),
+            content: lines[j + 1..end].concat(),
+            error: None,
+        });
+        i = end + 1;
+    }
+
+    Ok(files)
+}
+
+/// Return the backtick fence that opens a code block on this line, if any.
+fn opening_fence(line: &str) -> Option<&str> {
+    let ticks = line.len() - line.trim_start_matches('`').len();
+    (ticks >= 3).then(|| &line[..ticks])
+}
+
+/// Find the index of the line that closes a code block opened with `fence`.
+fn closing_fence(lines: &[&str], from: usize, fence: &str) -> Option<usize> {
+    (from..lines.len()).find(|&k| lines[k].trim_end_matches('\n') == fence)
+}
+
+fn parse_xml(input: &str) -> Result<Vec<MergedFile>> {
+    let mut files = Vec::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("<document ") {
+        let end = rest[start..]
+            .find("</document>")
+            .ok_or_else(|| Error::Unmerge("Unterminated <document> element".to_string()))?;
+        let document = &rest[start..start + end];
+        rest = &rest[start + end + "</document>".len()..];
+
+        let tag_end = document
+            .find('>')
+            .ok_or_else(|| Error::Unmerge("Malformed <document> element".to_string()))?;
+        let error = attribute(&document[..tag_end], "error").map(|e| unescape_xml(&e));
+
+        let path = element(document, "source")
+            .ok_or_else(|| Error::Unmerge("Missing <source> in <document>".to_string()))?;
+        let content = element(document, "document_content").unwrap_or_default();
+
+        files.push(MergedFile {
+            path: unescape_xml(path),
+            content: unescape_xml(content),
+            error,
+        });
+    }
+
+    Ok(files)
+}
+
+fn element<'a>(document: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = document.find(&open)? + open.len();
+    let end = document[start..].find(&close)? + start;
+    Some(&document[start..end])
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let key = format!(" {}=\"", name);
+    let start = tag.find(&key)? + key.len();
+    let end = tag[start..].find('"')? + start;
+    Some(tag[start..end].to_string())
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_json(input: &str) -> Result<Vec<MergedFile>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Document {
+        Files(Vec<MergedFile>),
+        Bundle { files: Vec<MergedFile> },
+    }
+
+    Ok(match serde_json::from_str(input)? {
+        Document::Files(files) | Document::Bundle { files } => files,
+    })
+}
+
+/// Make the paths of a bundle merged from an absolute or `..` root relative
+/// again, by removing the directory all of them share. Only used when asked
+/// for with `--strip-root`; otherwise such paths are refused.
+///
+/// Paths are left alone when none of them would escape the target directory.
+///
+/// # Returns
+///
+/// * `Option<PathBuf>` - The common root that was removed, if any.
+pub fn strip_common_root(files: &mut [MergedFile]) -> Option<PathBuf> {
+    let escapes = |path: &Path| {
+        path.components().any(|c| {
+            matches!(
+                c,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_)
+            )
+        })
+    };
+    if !files.iter().any(|f| escapes(Path::new(&f.path))) {
+        return None;
+    }
+
+    let mut common: Vec<Component> = Path::new(&files.first()?.path)
+        .parent()?
+        .components()
+        .collect();
+    for file in &files[1..] {
+        let parent: Vec<Component> = Path::new(&file.path)
+            .parent()
+            .map(|p| p.components().collect())
+            .unwrap_or_default();
+        let shared = common
+            .iter()
+            .zip(&parent)
+            .take_while(|(a, b)| a == b)
+            .count();
+        common.truncate(shared);
+    }
+    let root: PathBuf = common.iter().collect();
+    if root.as_os_str().is_empty() {
+        return None;
+    }
+
+    for file in files.iter_mut() {
+        if let Ok(relative) = Path::new(&file.path).strip_prefix(&root) {
+            file.path = relative.to_string_lossy().to_string();
+        }
+    }
+    Some(root)
+}
+
+/// Resolve a merged path below `target`, refusing absolute paths and `..`.
+pub fn resolve_target(target: &Path, path: &str) -> Result<PathBuf> {
+    let mut resolved = target.to_path_buf();
+    let mut has_name = false;
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+                has_name = true;
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::Unmerge(format!(
+                    "Refusing to write outside the target directory: {}",
+                    path
+                )))
+            }
+        }
+    }
+
+    if !has_name {
+        return Err(Error::Unmerge(format!("Invalid file path: '{}'", path)));
+    }
+    Ok(resolved)
+}
+
+/// Describe what writing the files would change, as unified diffs against
+/// the current contents of `target`.
+pub fn diff_files(files: &[MergedFile], target: &Path) -> Result<String> {
+    let mut output = String::new();
+
+    for file in files {
+        let destination = resolve_target(target, &file.path)?;
+        let current = if destination.exists() {
+            Some(std::fs::read_to_string(&destination)?)
+        } else {
+            None
+        };
+
+        let status = match &current {
+            None => "create",
+            Some(current) if *current == file.content => "unchanged",
+            Some(_) => "modify",
+        };
+        output.push_str(&format!("{}: {}\n", status, destination.display()));
+
+        if status != "unchanged" {
+            let old = current.unwrap_or_default();
+            let diff = TextDiff::from_lines(&old, &file.content);
+            output.push_str(
+                &diff
+                    .unified_diff()
+                    .header(&format!("a/{}", file.path), &format!("b/{}", file.path))
+                    .to_string(),
+            );
+        }
+    }
+
+    Ok(output)
+}
+
+/// Write the files below `target`, creating directories as needed.
+///
+/// Every destination is checked before anything is created: no directory on
+/// the way to a file, and no existing file it would replace, may be a
+/// symlink, so a write can never be redirected outside `target`.
+pub fn write_files(files: &[MergedFile], target: &Path) -> Result<Vec<PathBuf>> {
+    let destinations = files
+        .iter()
+        .map(|f| resolve_target(target, &f.path))
+        .collect::<Result<Vec<_>>>()?;
+    for (file, destination) in files.iter().zip(&destinations) {
+        prepare_destination(target, destination, &file.path, false)?;
+    }
+
+    std::fs::create_dir_all(target)?;
+    for (file, destination) in files.iter().zip(&destinations) {
+        prepare_destination(target, destination, &file.path, true)?;
+        std::fs::write(destination, &file.content)?;
+    }
+
+    Ok(destinations)
+}
+
+/// Check each existing component of `destination` below `target`, refusing
+/// symlinks and files where a directory is needed, and create the missing
+/// directories when `create` is set.
+fn prepare_destination(target: &Path, destination: &Path, path: &str, create: bool) -> Result<()> {
+    let relative = destination.strip_prefix(target).unwrap_or(destination);
+    let mut current = target.to_path_buf();
+    let mut components = relative.components().peekable();
+
+    while let Some(component) = components.next() {
+        current.push(component);
+        let is_file = components.peek().is_none();
+        let metadata = match std::fs::symlink_metadata(&current) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if create && !is_file {
+                    std::fs::create_dir(&current)?;
+                }
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if metadata.file_type().is_symlink() {
+            return Err(Error::Unmerge(format!(
+                "Refusing to write through the symlink {}: {}",
+                current.display(),
+                path
+            )));
+        }
+        if metadata.is_dir() == is_file {
+            return Err(Error::Unmerge(format!(
+                "Cannot write {}: {} is {}",
+                path,
+                current.display(),
+                if is_file {
+                    "a directory"
+                } else {
+                    "not a directory"
+                }
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::file::FileData;
+    use crate::core::tokenizer::Encoding;
+    use crate::utils::format::{self, OutputOptions};
+
+    fn sample_files() -> Vec<FileData> {
+        vec![
+            FileData::new(
+                "src/main.rs",
+                "fn main() {\n    println!(\"<&>\");\n}\n",
+                &Encoding::default(),
+            ),
+            FileData::new(
+                "docs/README.md",
+                "# Title\n\n```sh\ncargo run\n```\n\n## Not a file\n",
+                &Encoding::default(),
+            ),
+            FileData::new(
+                "data/config.xml",
+                "<a b=\"c\">]]></a>\n",
+                &Encoding::default(),
+            ),
+            FileData::new("d/x.txt", "\n\nlast line", &Encoding::default()),
+            FileData::new("d/empty.txt", "", &Encoding::default()),
+        ]
+    }
+
+    fn assert_round_trip(format_type: &str, options: &OutputOptions) {
+        let files = sample_files();
+        let rendered = format::render(&files, format_type, options).unwrap();
+        assert_eq!(
+            detect_format(&rendered),
+            format_type,
+            "format detection failed"
+        );
+
+        let parsed = parse(&rendered, "auto").unwrap();
+        let expected: Vec<(String, String)> = files
+            .iter()
+            .map(|f| {
+                // Text and Markdown end every file with a newline.
+                let mut content = f.content.clone();
+                if matches!(format_type, "text" | "markdown")
+                    && !content.is_empty()
+                    && !content.ends_with('\n')
+                {
+                    content.push('\n');
+                }
+                (f.path.clone(), content)
+            })
+            .collect();
+        let actual: Vec<(String, String)> =
+            parsed.into_iter().map(|f| (f.path, f.content)).collect();
+        assert_eq!(actual, expected, "round trip failed for {}", format_type);
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        for format_type in ["text", "markdown", "xml", "json"] {
+            assert_round_trip(format_type, &OutputOptions::default());
+        }
+
+        let with_preamble = OutputOptions {
+            with_tree: true,
+            with_stats: true,
+            with_meta: false,
+            tokenizer: &Encoding::default(),
+            errors: &[],
+        };
+        for format_type in ["text", "markdown", "json"] {
+            assert_round_trip(format_type, &with_preamble);
+        }
+    }
+
+    #[test]
+    fn test_text_edited_by_a_model() {
+        // a.txt gained a line and b.txt lost one, whatever the line count
+        // hints in the headers say.
+        let edited = "=== Result ===\n\
+                      File: a.txt (1 line)\none\ntwo\n\
+                      File: b.txt (3 lines)\nthree\n\
+                      File: c.txt\nfour\n\n";
+        let parsed = parse(edited, "auto").unwrap();
+        let files: Vec<(&str, &str)> = parsed
+            .iter()
+            .map(|f| (f.path.as_str(), f.content.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("a.txt", "one\ntwo\n"),
+                ("b.txt", "three\n"),
+                ("c.txt", "four\n")
+            ]
+        );
+
+        // Paths that merely end in parentheses are kept whole.
+        let parsed = parse("=== Result ===\nFile: notes (draft).txt\nx\n", "text").unwrap();
+        assert_eq!(parsed[0].path, "notes (draft).txt");
+
+        assert!(parse("=== Result ===\nstray line\n", "text").is_err());
+    }
+
+    #[test]
+    fn test_json_records_and_bundle() {
+        let files = sample_files();
+        let errors = vec![FileData::with_error("bad.bin", "Invalid UTF-8")];
+        let options = OutputOptions {
+            errors: &errors,
+            ..OutputOptions::default()
+        };
+
+        // The default array names the tokenizer on every record.
+        let rendered = format::render(&files, "json", &options).unwrap();
+        assert!(rendered.contains("\"tokenizer\": \"cl100k_base\""));
+        let parsed = parse(&rendered, "auto").unwrap();
+        assert_eq!(parsed.len(), files.len());
+        assert_eq!(parsed[0].path, "src/main.rs");
+
+        let with_meta = OutputOptions {
+            with_meta: true,
+            ..options
+        };
+        let rendered = format::render(&files, "json", &with_meta).unwrap();
+        assert_eq!(parse(&rendered, "auto").unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_skips_files_with_errors() {
+        let files = vec![
+            FileData::with_error("bad.bin", "Invalid UTF-8"),
+            FileData::new("good.txt", "ok\n", &Encoding::default()),
+        ];
+        let rendered = format::render(&files, "xml", &OutputOptions::default()).unwrap();
+        let parsed = parse(&rendered, "xml").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].path, "good.txt");
+    }
+
+    #[test]
+    fn test_resolve_target_refuses_escapes() {
+        let target = Path::new("/tmp/out");
+        assert_eq!(
+            resolve_target(target, "./src/main.rs").unwrap(),
+            PathBuf::from("/tmp/out/src/main.rs")
+        );
+        assert!(resolve_target(target, "../etc/passwd").is_err());
+        assert!(resolve_target(target, "src/../../escape").is_err());
+        assert!(resolve_target(target, "/etc/passwd").is_err());
+        assert!(resolve_target(target, ".").is_err());
+    }
+
+    #[test]
+    fn test_refuses_bundles_merged_from_outside() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("out");
+        let bundle = "[{\"path\": \"/abs/root/a.rs\", \"content\": \"a\"}, \
+                      {\"path\": \"/abs/root/b.rs\", \"content\": \"b\"}]";
+        let files = parse(bundle, "json").unwrap();
+
+        for error in [
+            write_files(&files, &target).unwrap_err(),
+            diff_files(&files, &target).unwrap_err(),
+        ] {
+            assert!(matches!(error, Error::Unmerge(_)));
+            assert!(error.to_string().contains("/abs/root/a.rs"));
+        }
+        assert!(!target.exists());
+
+        let files = parse("[{\"path\": \"../x/a.rs\", \"content\": \"a\"}]", "json").unwrap();
+        let error = write_files(&files, &target).unwrap_err();
+        assert!(error.to_string().contains("../x/a.rs"));
+    }
+
+    #[test]
+    fn test_strip_common_root() {
+        let file = |path: &str| MergedFile {
+            path: path.to_string(),
+            content: String::new(),
+            error: None,
+        };
+
+        let mut files = vec![file("/abs/root/src/main.rs"), file("/abs/root/README.md")];
+        assert_eq!(
+            strip_common_root(&mut files),
+            Some(PathBuf::from("/abs/root"))
+        );
+        assert_eq!(files[0].path, "src/main.rs");
+        assert_eq!(files[1].path, "README.md");
+
+        let mut files = vec![file("../x/a.rs"), file("../x/b/c.rs")];
+        assert_eq!(strip_common_root(&mut files), Some(PathBuf::from("../x")));
+        assert_eq!(files[1].path, "b/c.rs");
+
+        let mut files = vec![file("./src/a.rs"), file("./src/b.rs")];
+        assert_eq!(strip_common_root(&mut files), None);
+        assert_eq!(files[0].path, "./src/a.rs");
+
+        // Nothing is shared, so the escaping path is still refused later.
+        let mut files = vec![file("../x/a.rs"), file("src/b.rs")];
+        assert_eq!(strip_common_root(&mut files), None);
+        assert!(resolve_target(Path::new("out"), &files[0].path).is_err());
+    }
+
+    #[test]
+    fn test_write_and_diff() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path();
+        let files = parse(
+            &format::render(&sample_files(), "markdown", &OutputOptions::default()).unwrap(),
+            "markdown",
+        )
+        .unwrap();
+
+        let diff = diff_files(&files, target).unwrap();
+        assert!(diff.contains("create: "));
+        assert!(!target.join("src/main.rs").exists());
+
+        write_files(&files, target).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(target.join("src/main.rs")).unwrap(),
+            files[0].content
+        );
+
+        std::fs::write(target.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let diff = diff_files(&files, target).unwrap();
+        assert!(diff.contains("modify: "));
+        assert!(diff.contains("-fn main() {}"));
+        assert!(diff.contains("unchanged: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_refuses_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        let target = temp_dir.path().join("out");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(outside.join("victim.txt"), "safe").unwrap();
+        symlink(outside.join("victim.txt"), target.join("victim.txt")).unwrap();
+        symlink(&outside, target.join("linked")).unwrap();
+
+        let file = |path: &str| MergedFile {
+            path: path.to_string(),
+            content: "pwned".to_string(),
+            error: None,
+        };
+        assert!(write_files(&[file("victim.txt")], &target).is_err());
+        assert!(write_files(&[file("linked/new/file.txt")], &target).is_err());
+        // Nothing is written when any destination is refused.
+        assert!(write_files(&[file("ok.txt"), file("linked/victim.txt")], &target).is_err());
+
+        assert_eq!(
+            std::fs::read_to_string(outside.join("victim.txt")).unwrap(),
+            "safe"
+        );
+        assert!(!outside.join("new").exists());
+        assert!(!target.join("ok.txt").exists());
+    }
+
+    #[test]
+    fn test_write_refuses_escape() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let files = vec![MergedFile {
+            path: "../escape.txt".to_string(),
+            content: "nope".to_string(),
+            error: None,
+        }];
+        assert!(write_files(&files, &temp_dir.path().join("out")).is_err());
+        assert!(!temp_dir.path().join("escape.txt").exists());
+    }
+}
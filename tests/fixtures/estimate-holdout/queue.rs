@@ -0,0 +1,101 @@
+use std::collections::BinaryHeap;
+use std::cmp::{Ordering, Reverse};
+use std::time::{Duration, Instant};
+
+/// A job waiting to be run, ordered by its deadline and then by priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id: u64,
+    pub priority: u8,
+    pub deadline: Instant,
+    pub attempts: u32,
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Reverse(self.deadline)
+            .cmp(&Reverse(other.deadline))
+            .then_with(|| self.priority.cmp(&other.priority))
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Queue {
+    heap: BinaryHeap<Job>,
+    next_id: u64,
+    max_attempts: u32,
+}
+
+impl Queue {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_id: 0,
+            max_attempts,
+        }
+    }
+
+    pub fn push(&mut self, priority: u8, timeout: Duration) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.heap.push(Job {
+            id,
+            priority,
+            deadline: Instant::now() + timeout,
+            attempts: 0,
+        });
+        id
+    }
+
+    /// Take the most urgent job, skipping any whose deadline has passed.
+    pub fn pop(&mut self, now: Instant) -> Option<Job> {
+        while let Some(job) = self.heap.pop() {
+            if job.deadline >= now {
+                return Some(job);
+            }
+        }
+        None
+    }
+
+    /// Put a failed job back with a backoff, unless it ran out of attempts.
+    pub fn retry(&mut self, mut job: Job, backoff: Duration) -> Result<(), Job> {
+        job.attempts += 1;
+        if job.attempts >= self.max_attempts {
+            return Err(job);
+        }
+        job.deadline += backoff * 2u32.pow(job.attempts.min(10));
+        self.heap.push(job);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earlier_deadline_wins() {
+        let mut queue = Queue::new(3);
+        let slow = queue.push(9, Duration::from_secs(60));
+        let fast = queue.push(1, Duration::from_secs(1));
+        let now = Instant::now();
+        assert_eq!(queue.pop(now).unwrap().id, fast);
+        assert_eq!(queue.pop(now).unwrap().id, slow);
+        assert!(queue.pop(now).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "ring_buffer.h"
+
+/*
+ * A fixed-size single-producer, single-consumer ring buffer.
+ *
+ * The capacity is always a power of two so that indices can be wrapped
+ * with a mask instead of a modulo. One slot is never used, which keeps
+ * the full and empty states distinguishable without a separate counter.
+ */
+
+struct ring_buffer {
+    uint8_t *data;
+    size_t mask;
+    size_t head;
+    size_t tail;
+};
+
+static size_t next_power_of_two(size_t n)
+{
+    size_t p = 1;
+    while (p < n) {
+        p <<= 1;
+    }
+    return p;
+}
+
+struct ring_buffer *ring_buffer_new(size_t capacity)
+{
+    struct ring_buffer *rb = calloc(1, sizeof(*rb));
+    if (rb == NULL) {
+        return NULL;
+    }
+
+    size_t size = next_power_of_two(capacity + 1);
+    rb->data = malloc(size);
+    if (rb->data == NULL) {
+        free(rb);
+        return NULL;
+    }
+    rb->mask = size - 1;
+    return rb;
+}
+
+void ring_buffer_free(struct ring_buffer *rb)
+{
+    if (rb != NULL) {
+        free(rb->data);
+        free(rb);
+    }
+}
+
+size_t ring_buffer_len(const struct ring_buffer *rb)
+{
+    return (rb->head - rb->tail) & rb->mask;
+}
+
+size_t ring_buffer_space(const struct ring_buffer *rb)
+{
+    return rb->mask - ring_buffer_len(rb);
+}
+
+size_t ring_buffer_write(struct ring_buffer *rb, const uint8_t *src, size_t len)
+{
+    size_t space = ring_buffer_space(rb);
+    if (len > space) {
+        len = space;
+    }
+
+    size_t head = rb->head & rb->mask;
+    size_t first = rb->mask + 1 - head;
+    if (first > len) {
+        first = len;
+    }
+    memcpy(rb->data + head, src, first);
+    memcpy(rb->data, src + first, len - first);
+    rb->head += len;
+    return len;
+}
+
+size_t ring_buffer_read(struct ring_buffer *rb, uint8_t *dst, size_t len)
+{
+    size_t available = ring_buffer_len(rb);
+    if (len > available) {
+        len = available;
+    }
+
+    size_t tail = rb->tail & rb->mask;
+    size_t first = rb->mask + 1 - tail;
+    if (first > len) {
+        first = len;
+    }
+    memcpy(dst, rb->data + tail, first);
+    memcpy(dst + first, rb->data, len - first);
+    rb->tail += len;
+    return len;
+}
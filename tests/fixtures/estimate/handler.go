@@ -0,0 +1,101 @@
+package orders
+
+import (
+	"context"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"net/http"
+	"strconv"
+	"time"
+)
+
+// Order is a customer order as returned by the API.
+type Order struct {
+	ID        int64     `json:"id"`
+	Customer  string    `json:"customer"`
+	Items     []Item    `json:"items"`
+	Total     float64   `json:"total"`
+	Status    string    `json:"status"`
+	CreatedAt time.Time `json:"created_at"`
+}
+
+// Item is a single order line.
+type Item struct {
+	SKU      string  `json:"sku"`
+	Quantity int     `json:"quantity"`
+	Price    float64 `json:"price"`
+}
+
+// Store loads and saves orders.
+type Store interface {
+	Get(ctx context.Context, id int64) (*Order, error)
+	UpdateStatus(ctx context.Context, id int64, status string) error
+}
+
+var ErrNotFound = errors.New("order not found")
+
+// Handler serves the orders endpoints.
+type Handler struct {
+	store   Store
+	timeout time.Duration
+}
+
+func NewHandler(store Store) *Handler {
+	return &Handler{store: store, timeout: 3 * time.Second}
+}
+
+func (h *Handler) ServeHTTP(w http.ResponseWriter, r *http.Request) {
+	id, err := strconv.ParseInt(r.URL.Query().Get("id"), 10, 64)
+	if err != nil || id <= 0 {
+		http.Error(w, "invalid order id", http.StatusBadRequest)
+		return
+	}
+
+	ctx, cancel := context.WithTimeout(r.Context(), h.timeout)
+	defer cancel()
+
+	switch r.Method {
+	case http.MethodGet:
+		h.get(ctx, w, id)
+	case http.MethodPatch:
+		h.patch(ctx, w, r, id)
+	default:
+		w.Header().Set("Allow", "GET, PATCH")
+		http.Error(w, "method not allowed", http.StatusMethodNotAllowed)
+	}
+}
+
+func (h *Handler) get(ctx context.Context, w http.ResponseWriter, id int64) {
+	order, err := h.store.Get(ctx, id)
+	if errors.Is(err, ErrNotFound) {
+		http.Error(w, err.Error(), http.StatusNotFound)
+		return
+	} else if err != nil {
+		http.Error(w, fmt.Sprintf("loading order %d: %v", id, err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_ = json.NewEncoder(w).Encode(order)
+}
+
+func (h *Handler) patch(ctx context.Context, w http.ResponseWriter, r *http.Request, id int64) {
+	var body struct {
+		Status string `json:"status"`
+	}
+	if err := json.NewDecoder(r.Body).Decode(&body); err != nil {
+		http.Error(w, "invalid JSON body", http.StatusBadRequest)
+		return
+	}
+	switch body.Status {
+	case "paid", "shipped", "cancelled":
+	default:
+		http.Error(w, "unsupported status "+strconv.Quote(body.Status), http.StatusUnprocessableEntity)
+		return
+	}
+	if err := h.store.UpdateStatus(ctx, id, body.Status); err != nil {
+		http.Error(w, err.Error(), http.StatusInternalServerError)
+		return
+	}
+	w.WriteHeader(http.StatusNoContent)
+}
@@ -0,0 +1,93 @@
+"""Nightly data pipeline: load raw events, clean them and write daily aggregates."""
+
+from __future__ import annotations
+
+import csv
+import datetime as dt
+import logging
+from collections import Counter, defaultdict
+from dataclasses import dataclass, field
+from pathlib import Path
+from typing import Iterable, Iterator
+
+log = logging.getLogger(__name__)
+
+VALID_KINDS = {"click", "view", "purchase", "refund"}
+
+
+@dataclass
+class Event:
+    user_id: str
+    kind: str
+    amount: float
+    timestamp: dt.datetime
+    tags: list[str] = field(default_factory=list)
+
+    @property
+    def day(self) -> dt.date:
+        return self.timestamp.date()
+
+
+def read_events(path: Path) -> Iterator[Event]:
+    """Yield events from a CSV export, skipping malformed rows."""
+    with path.open(newline="", encoding="utf-8") as handle:
+        for line_no, row in enumerate(csv.DictReader(handle), start=2):
+            try:
+                yield Event(
+                    user_id=row["user_id"].strip(),
+                    kind=row["kind"].strip().lower(),
+                    amount=float(row.get("amount") or 0.0),
+                    timestamp=dt.datetime.fromisoformat(row["timestamp"]),
+                    tags=[t for t in row.get("tags", "").split("|") if t],
+                )
+            except (KeyError, ValueError) as exc:
+                log.warning("%s:%d: skipping row (%s)", path, line_no, exc)
+
+
+def clean(events: Iterable[Event]) -> Iterator[Event]:
+    seen: set[tuple[str, dt.datetime]] = set()
+    for event in events:
+        if event.kind not in VALID_KINDS:
+            continue
+        key = (event.user_id, event.timestamp)
+        if key in seen:
+            continue
+        seen.add(key)
+        yield event
+
+
+def aggregate(events: Iterable[Event]) -> dict[dt.date, dict[str, float]]:
+    totals: dict[dt.date, dict[str, float]] = defaultdict(lambda: defaultdict(float))
+    users: dict[dt.date, set[str]] = defaultdict(set)
+    for event in events:
+        day = totals[event.day]
+        day[f"{event.kind}_count"] += 1
+        if event.kind in ("purchase", "refund"):
+            sign = 1 if event.kind == "purchase" else -1
+            day["revenue"] += sign * event.amount
+        users[event.day].add(event.user_id)
+    for day, ids in users.items():
+        totals[day]["active_users"] = len(ids)
+    return totals
+
+
+def top_tags(events: Iterable[Event], limit: int = 10) -> list[tuple[str, int]]:
+    return Counter(tag for event in events for tag in event.tags).most_common(limit)
+
+
+def write_report(totals: dict[dt.date, dict[str, float]], out: Path) -> None:
+    columns = sorted({key for day in totals.values() for key in day})
+    with out.open("w", newline="", encoding="utf-8") as handle:
+        writer = csv.writer(handle)
+        writer.writerow(["day", *columns])
+        for day in sorted(totals):
+            writer.writerow([day.isoformat(), *(round(totals[day].get(c, 0), 2) for c in columns)])
+    log.info("wrote %d days to %s", len(totals), out)
+
+
+if __name__ == "__main__":
+    logging.basicConfig(level=logging.INFO, format="%(asctime)s %(levelname)s %(message)s")
+    events = list(clean(read_events(Path("exports/events.csv"))))
+    write_report(aggregate(events), Path("reports/daily.csv"))
+    for tag, count in top_tags(events):
+        print(f"{tag:<20} {count:>8}")
@@ -0,0 +1,57 @@
+#!/usr/bin/env bash
+# Build release artifacts for every supported target and publish checksums.
+set -euo pipefail
+
+VERSION="${1:?usage: release.sh <version>}"
+DIST_DIR="dist/${VERSION}"
+TARGETS=(
+  x86_64-unknown-linux-gnu
+  aarch64-unknown-linux-gnu
+  x86_64-apple-darwin
+  aarch64-apple-darwin
+)
+
+log() { printf '\033[1;34m==>\033[0m %s\n' "$*"; }
+die() { printf '\033[1;31merror:\033[0m %s\n' "$*" >&2; exit 1; }
+
+command -v cross >/dev/null 2>&1 || die "cross is not installed (cargo install cross)"
+[[ -z "$(git status --porcelain)" ]] || die "working tree is dirty"
+
+if ! grep -q "^version = \"${VERSION}\"" Cargo.toml; then
+  die "Cargo.toml version does not match ${VERSION}"
+fi
+
+rm -rf "${DIST_DIR}"
+mkdir -p "${DIST_DIR}"
+
+for target in "${TARGETS[@]}"; do
+  log "building ${target}"
+  cross build --release --locked --target "${target}"
+
+  archive="codemerge-${VERSION}-${target}.tar.gz"
+  staging="$(mktemp -d)"
+  cp "target/${target}/release/codemerge" README.md LICENSE "${staging}/"
+  tar -C "${staging}" -czf "${DIST_DIR}/${archive}" .
+  rm -rf "${staging}"
+done
+
+log "writing checksums"
+(
+  cd "${DIST_DIR}"
+  if command -v sha256sum >/dev/null 2>&1; then
+    sha256sum ./*.tar.gz > SHA256SUMS
+  else
+    shasum -a 256 ./*.tar.gz > SHA256SUMS
+  fi
+)
+
+if [[ "${PUBLISH:-0}" == "1" ]]; then
+  log "tagging v${VERSION}"
+  git tag -s "v${VERSION}" -m "codemerge ${VERSION}"
+  git push origin "v${VERSION}"
+  gh release create "v${VERSION}" "${DIST_DIR}"/* --title "v${VERSION}" --notes-file CHANGELOG.md
+else
+  log "dry run finished; set PUBLISH=1 to tag and upload"
+fi
+
+ls -lh "${DIST_DIR}"
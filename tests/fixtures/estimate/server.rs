@@ -0,0 +1,100 @@
+//! A small HTTP health-check server used by the integration environment.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Per-route request counters, shared between worker threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: HashMap<String, u64>,
+    started: Option<Instant>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, route: &str) {
+        *self.requests.entry(route.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.map(|s| s.elapsed()).unwrap_or_default()
+    }
+}
+
+fn handle(stream: TcpStream, metrics: Arc<Mutex<Metrics>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let route = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .to_string();
+    metrics.lock().unwrap().record(&route);
+
+    let (status, body) = match route.as_str() {
+        "/health" => ("200 OK", "ok".to_string()),
+        "/metrics" => {
+            let metrics = metrics.lock().unwrap();
+            let mut lines: Vec<String> = metrics
+                .requests
+                .iter()
+                .map(|(route, count)| format!("requests{{route=\"{}\"}} {}", route, count))
+                .collect();
+            lines.sort();
+            lines.push(format!("uptime_seconds {}", metrics.uptime().as_secs()));
+            ("200 OK", lines.join("\n"))
+        }
+        _ => ("404 Not Found", format!("no route for {}", route)),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: text/plain\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn main() -> std::io::Result<()> {
+    let port: u16 = std::env::var("PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(8080);
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let metrics = Arc::new(Mutex::new(Metrics {
+        started: Some(Instant::now()),
+        ..Default::default()
+    }));
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let metrics = Arc::clone(&metrics);
+        std::thread::spawn(move || {
+            if let Err(e) = handle(stream, metrics) {
+                eprintln!("request failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_routes() {
+        let mut metrics = Metrics::default();
+        metrics.record("/health");
+        metrics.record("/health");
+        assert_eq!(metrics.requests["/health"], 2);
+    }
+}
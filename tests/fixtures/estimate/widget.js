@@ -0,0 +1,111 @@
+// Autocomplete widget: debounced fetch, keyboard navigation and highlighting.
+
+const DEFAULT_OPTIONS = {
+  minLength: 2,
+  debounceMs: 150,
+  maxResults: 8,
+  endpoint: "/api/search",
+};
+
+function debounce(fn, wait) {
+  let timer = null;
+  return (...args) => {
+    clearTimeout(timer);
+    timer = setTimeout(() => fn(...args), wait);
+  };
+}
+
+function escapeHtml(text) {
+  return text.replace(/[&<>"']/g, (c) => ({
+    "&": "&amp;",
+    "<": "&lt;",
+    ">": "&gt;",
+    '"': "&quot;",
+    "'": "&#39;",
+  })[c]);
+}
+
+export class Autocomplete {
+  constructor(input, options = {}) {
+    this.input = input;
+    this.options = { ...DEFAULT_OPTIONS, ...options };
+    this.results = [];
+    this.active = -1;
+    this.list = document.createElement("ul");
+    this.list.className = "autocomplete-list";
+    this.list.setAttribute("role", "listbox");
+    input.after(this.list);
+
+    this.search = debounce(this.search.bind(this), this.options.debounceMs);
+    input.addEventListener("input", () => this.search(input.value.trim()));
+    input.addEventListener("keydown", (event) => this.onKeyDown(event));
+    document.addEventListener("click", (event) => {
+      if (!this.list.contains(event.target) && event.target !== input) {
+        this.close();
+      }
+    });
+  }
+
+  async search(query) {
+    if (query.length < this.options.minLength) {
+      this.close();
+      return;
+    }
+    const url = `${this.options.endpoint}?q=${encodeURIComponent(query)}&limit=${this.options.maxResults}`;
+    try {
+      const response = await fetch(url, { headers: { Accept: "application/json" } });
+      if (!response.ok) throw new Error(`HTTP ${response.status}`);
+      const { items = [] } = await response.json();
+      this.results = items;
+      this.render(query);
+    } catch (error) {
+      console.warn("autocomplete request failed", error);
+      this.close();
+    }
+  }
+
+  render(query) {
+    const pattern = new RegExp(`(${query.replace(/[.*+?^${}()|[\]\\]/g, "\\$&")})`, "ig");
+    this.list.innerHTML = this.results
+      .map((item, i) => {
+        const label = escapeHtml(item.label).replace(pattern, "<mark>$1</mark>");
+        return `<li role="option" data-index="${i}" class="${i === this.active ? "active" : ""}">${label}</li>`;
+      })
+      .join("");
+    this.list.hidden = this.results.length === 0;
+  }
+
+  onKeyDown(event) {
+    if (this.list.hidden) return;
+    switch (event.key) {
+      case "ArrowDown":
+        this.active = (this.active + 1) % this.results.length;
+        break;
+      case "ArrowUp":
+        this.active = (this.active - 1 + this.results.length) % this.results.length;
+        break;
+      case "Enter":
+        if (this.active >= 0) this.select(this.results[this.active]);
+        return;
+      case "Escape":
+        this.close();
+        return;
+      default:
+        return;
+    }
+    event.preventDefault();
+    this.render(this.input.value.trim());
+  }
+
+  select(item) {
+    this.input.value = item.label;
+    this.input.dispatchEvent(new CustomEvent("autocomplete:select", { detail: item }));
+    this.close();
+  }
+
+  close() {
+    this.results = [];
+    this.active = -1;
+    this.list.hidden = true;
+  }
+}